@@ -0,0 +1,7 @@
+attribute vec3 position;
+uniform mat4 lightMatrix;
+uniform mat4 Mmatrix;
+
+void main() {
+    gl_Position = lightMatrix * Mmatrix * vec4(position, 1.);
+}
@@ -0,0 +1,7 @@
+precision mediump float;
+uniform sampler2D depthMap;
+varying vec2 vUv;
+
+void main() {
+    gl_FragColor = vec4(vec3(texture2D(depthMap, vUv).r), 1.);
+}
@@ -0,0 +1,19 @@
+precision mediump float;
+uniform vec3 topColor;
+uniform vec3 bottomColor;
+uniform bool dither;
+varying vec2 vUv;
+
+// See scene.frag.glsl.
+vec3 ditherNoise() {
+    float noise = fract(52.9829189 * fract(dot(gl_FragCoord.xy, vec2(0.06711056, 0.00583715))));
+    return vec3((noise - 0.5) / 255.);
+}
+
+void main() {
+    vec3 color = mix(bottomColor, topColor, vUv.y);
+    if (dither) {
+        color += ditherNoise();
+    }
+    gl_FragColor = vec4(color, 1.);
+}
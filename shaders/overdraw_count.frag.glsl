@@ -0,0 +1,6 @@
+precision mediump float;
+
+void main() {
+    // Saturates after 16 layers.
+    gl_FragColor = vec4(1. / 16.);
+}
@@ -0,0 +1,9 @@
+precision mediump float;
+uniform sampler2D counts;
+varying vec2 vUv;
+
+void main() {
+    // Black -> red -> yellow -> white as the layer count rises.
+    float t = texture2D(counts, vUv).r * 3.;
+    gl_FragColor = vec4(clamp(vec3(t, t - 1., t - 2.), 0., 1.), 1.);
+}
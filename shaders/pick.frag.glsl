@@ -0,0 +1,6 @@
+precision mediump float;
+uniform vec4 id;
+
+void main() {
+    gl_FragColor = id;
+}
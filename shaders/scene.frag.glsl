@@ -0,0 +1,131 @@
+precision mediump float;
+// Must match MAX_LIGHTS in main.rs.
+#define MAX_LIGHTS 4
+// Point lights; only the first casts shadows.
+uniform vec3 lightPositions[MAX_LIGHTS];
+uniform vec3 lightColors[MAX_LIGHTS];
+uniform int numLights;
+uniform vec3 viewPos;
+uniform float shininess;
+uniform bool shadows;
+uniform sampler2D shadowMap;
+uniform float shadowBias;
+uniform float shadowTexelSize;
+uniform float exposure;
+uniform int tonemapOperator;
+uniform bool dither;
+uniform vec3 colorScale;
+// Share of the base color lit by a flat fill light; the lights' diffuse term gets the rest.
+uniform float ambient;
+uniform vec3 ambientColor;
+// Per-mesh tint, multiplied into the vertex colors.
+uniform vec3 materialColor;
+// Per-mesh tint for the specular highlights, independent of the diffuse color.
+uniform vec3 specularColor;
+uniform float objectAlpha;
+// Set while drawing points, to trim them from squares to circles.
+uniform bool roundPoints;
+// Set while drawing a wire overlay, to draw the edges flat in wireColor.
+uniform bool wireOverlay;
+uniform vec3 wireColor;
+// Whether to modulate the vertex colors by baseTexture.
+uniform bool textured;
+uniform sampler2D baseTexture;
+// 0 for normal shading, or 1/2 to show model-/world-space normals as colors.
+uniform int normalDisplay;
+varying vec3 vColor;
+varying float vAlpha;
+varying vec2 vUv;
+varying float vColorW;
+varying vec3 vNormal;
+varying vec3 vModelNormal;
+varying vec3 vWorldNormal;
+varying vec3 vFragPos;
+varying vec4 vLightSpacePos;
+
+// Fraction of the light reaching this fragment, averaged over a 3x3 neighbourhood
+// of shadow map texels (percentage-closer filtering) to soften the edges.
+float lightVisibility() {
+    if (!shadows || vLightSpacePos.w <= 0.) {
+        return 1.;
+    }
+    vec3 coords = vLightSpacePos.xyz / vLightSpacePos.w * 0.5 + 0.5;
+    if (any(lessThan(coords, vec3(0.))) || any(greaterThan(coords, vec3(1.)))) {
+        // Outside the light's frustum - treat as fully lit.
+        return 1.;
+    }
+    float visible = 0.;
+    for (int x = -1; x <= 1; x++) {
+        for (int y = -1; y <= 1; y++) {
+            vec2 offset = vec2(float(x), float(y)) * shadowTexelSize;
+            float depth = texture2D(shadowMap, coords.xy + offset).r;
+            visible += coords.z - shadowBias > depth ? 0. : 1.;
+        }
+    }
+    return visible / 9.;
+}
+
+// Compresses exposed color into [0, 1] so bright highlights roll off rather than clip.
+vec3 tonemap(vec3 color) {
+    color *= exposure;
+    if (tonemapOperator == 1) {
+        // Narkowicz's fit of the ACES filmic curve.
+        return clamp(
+            (color * (2.51 * color + 0.03)) / (color * (2.43 * color + 0.59) + 0.14),
+            0.,
+            1.);
+    }
+    return color / (1. + color);
+}
+
+// Interleaved gradient noise (Jimenez 2014) in [-0.5, 0.5] output LSBs, added to break up
+// banding in smooth gradients on 8-bit displays.
+vec3 ditherNoise() {
+    float noise = fract(52.9829189 * fract(dot(gl_FragCoord.xy, vec2(0.06711056, 0.00583715))));
+    return vec3((noise - 0.5) / 255.);
+}
+
+void main() {
+    if (roundPoints && length(gl_PointCoord - 0.5) > 0.5) {
+        discard;
+    }
+    if (wireOverlay) {
+        gl_FragColor = vec4(wireColor, 1.);
+        return;
+    }
+    if (normalDisplay != 0) {
+        vec3 n = normalize(normalDisplay == 1 ? vModelNormal : vWorldNormal);
+        gl_FragColor = vec4(0.5 + 0.5 * n, 1.0);
+        return;
+    }
+
+    vec3 color = vColor / vColorW * colorScale * materialColor;
+    if (textured) {
+        color *= texture2D(baseTexture, vUv).rgb;
+    }
+    vec3 normal = normalize(vWorldNormal);
+    vec3 viewDir = normalize(viewPos - vFragPos);
+    vec3 diffuse = vec3(0.);
+    vec3 specular = vec3(0.);
+    for (int i = 0; i < MAX_LIGHTS; i++) {
+        if (i >= numLights) {
+            break;
+        }
+        vec3 lightDir = normalize(lightPositions[i] - vFragPos);
+        float visibility = i == 0 ? lightVisibility() : 1.;
+        diffuse += max(dot(vNormal, lightDir), 0.0) * visibility * lightColors[i];
+        // Phong specular: highlights where the light reflects towards the camera.
+        vec3 reflectDir = reflect(-lightDir, normal);
+        float highlight = dot(normal, lightDir) > 0.
+            ? pow(max(dot(viewDir, reflectDir), 0.0), shininess) * visibility
+            : 0.;
+        specular += highlight * lightColors[i];
+    }
+    color = tonemap(
+        color * (ambient * ambientColor + (1. - ambient) * diffuse)
+        + 0.5 * specular * specularColor);
+    if (dither) {
+        color += ditherNoise();
+    }
+    gl_FragColor = vec4(color, objectAlpha * vAlpha);
+}
@@ -0,0 +1,39 @@
+attribute vec3 position;
+attribute vec3 normal;
+uniform mat4 Pmatrix;
+uniform mat4 Vmatrix;
+uniform mat4 Mmatrix;
+uniform mat3 Nmatrix;
+uniform mat4 lightMatrix;
+uniform bool affineColor;
+// Diameter in pixels when drawing point clouds; ignored for triangles.
+uniform float pointSize;
+attribute vec3 color;
+attribute float alpha;
+attribute vec2 uv;
+varying vec3 vColor;
+varying float vAlpha;
+varying vec2 vUv;
+varying float vColorW;
+varying vec3 vNormal;
+varying vec3 vModelNormal;
+varying vec3 vWorldNormal;
+varying vec3 vFragPos;
+varying vec4 vLightSpacePos;
+
+void main() {
+    vFragPos = vec3(Mmatrix * vec4(position, 1.));
+    gl_Position = Pmatrix*Vmatrix*vec4(vFragPos, 1.);
+    gl_PointSize = pointSize;
+    vLightSpacePos = lightMatrix * vec4(vFragPos, 1.);
+    vNormal = vec3(Mmatrix * vec4(normal, 1.));
+    vModelNormal = normal;
+    vWorldNormal = Nmatrix * normal;
+    // Varyings are always interpolated perspective-correctly, i.e. as v/w.  Scaling
+    // both the color and a divisor by w cancels that out, giving affine
+    // interpolation once the fragment shader divides one by the other.
+    vColorW = affineColor ? gl_Position.w : 1.;
+    vColor = color * vColorW;
+    vAlpha = alpha;
+    vUv = uv;
+}
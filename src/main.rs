@@ -6,20 +6,41 @@ extern crate ply_rs;
 extern crate stdweb;
 extern crate webgl;
 
-use std::cell::RefCell;
-use std::io::Read;
+use std::borrow::Cow;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::iter;
+use std::mem;
 use std::rc::Rc;
+use std::slice;
 
 use stdweb::unstable::TryInto;
-use stdweb::web::{document, window, IEventTarget, IHtmlElement, IParentNode, TypedArray};
+use stdweb::{Once, Reference};
+use stdweb::web::{
+    document, window, ArrayBuffer, Element, IElement, IEventTarget, IHtmlElement, INode,
+    IParentNode, Touch, TypedArray, XmlHttpRequest,
+};
 
-use stdweb::web::event::{IKeyboardEvent, KeyDownEvent, KeyUpEvent, ResizeEvent};
+use stdweb::web::event::{
+    BlurEvent, ClickEvent, IEvent, IKeyboardEvent, IMouseEvent, ITouchEvent, KeyDownEvent,
+    KeyUpEvent, LoadEndEvent, MouseButton, MouseDownEvent, MouseMoveEvent, MouseUpEvent,
+    ResizeEvent, TouchCancel, TouchEnd, TouchMove, TouchStart,
+};
 
 use stdweb::web::html_element::{CanvasElement, ImageElement};
 use webgl::WebGLRenderingContext as gl;
-use webgl::{WebGLBuffer, WebGLProgram, WebGLRenderingContext, WebGLUniformLocation};
+use webgl::{
+    WebGLBuffer, WebGLFramebuffer, WebGLProgram, WebGLRenderbuffer, WebGLRenderingContext,
+    WebGLShader, WebGLTexture, WebGLUniformLocation,
+};
 
-use cgmath::{vec3, Deg, Euler, Matrix4, PerspectiveFov, Rad};
+use cgmath::{
+    vec3, vec4, Deg, ElementWise, EuclideanSpace, Euler, InnerSpace, Matrix, Matrix3, Matrix4,
+    MetricSpace, PerspectiveFov, Point3, Rad, SquareMatrix, Transform, Vector3,
+};
 
 trait Mesh {
     fn vertices(&self) -> &[f32];
@@ -27,7 +48,69 @@ trait Mesh {
     fn colors(&self) -> &[f32];
     fn indices(&self) -> &[u16];
 
+    /// Per-vertex opacity in [0, 1], or empty if the mesh is fully opaque.
+    fn alphas(&self) -> &[f32] {
+        &[]
+    }
+
+    /// Per-vertex texture coordinates, two per vertex, or empty if the mesh isn't textured.
+    fn texcoords(&self) -> &[f32] {
+        &[]
+    }
+
+    /// 32-bit indices, for meshes with too many vertices for `indices` (which is then empty).
+    /// Drawing these needs the `OES_element_index_uint` extension.
+    fn wide_indices(&self) -> &[u32] {
+        &[]
+    }
+
+    /// The same triangles as `indices`, as a single triangle strip (rows joined by degenerate
+    /// triangles rather than primitive restart), for meshes that can be drawn that way with
+    /// fewer indices; empty otherwise.  Only regular grids like `Plane` can be stripped so
+    /// simply, so arbitrary meshes leave this empty and stay on `gl::TRIANGLES`.
+    fn strip_indices(&self) -> &[u16] {
+        &[]
+    }
+
+    /// Each triangle's vertex indices, from whichever of `indices` and `wide_indices` the mesh
+    /// uses.
+    fn triangles<'a>(&'a self) -> Box<dyn Iterator<Item = [u32; 3]> + 'a> {
+        let wide = self.wide_indices();
+        if wide.is_empty() {
+            Box::new(
+                self.indices()
+                    .chunks(3)
+                    .filter(|t| t.len() == 3)
+                    .map(|t| [t[0] as u32, t[1] as u32, t[2] as u32]),
+            )
+        } else {
+            Box::new(
+                wide.chunks(3)
+                    .filter(|t| t.len() == 3)
+                    .map(|t| [t[0], t[1], t[2]]),
+            )
+        }
+    }
+
+    /// How to draw the mesh: as triangles (in a strip, if it has `strip_indices`), or as
+    /// points if it has vertices but no indices at all (a point cloud).
+    fn primitive(&self) -> PrimitiveType {
+        let indexed = !self.indices().is_empty() || !self.wide_indices().is_empty();
+        if !self.strip_indices().is_empty() {
+            PrimitiveType::TriangleStrip
+        } else if !indexed && !self.vertices().is_empty() {
+            PrimitiveType::Points
+        } else {
+            PrimitiveType::Triangles
+        }
+    }
+
     fn bind(&self, context: &WebGLRenderingContext) -> BoundMesh {
+        self.bind_with(context, ColorFormat::Float)
+    }
+
+    /// Like `bind`, but choosing how the colors are stored on the GPU.
+    fn bind_with(&self, context: &WebGLRenderingContext, color_format: ColorFormat) -> BoundMesh {
         let vertices = TypedArray::<f32>::from(self.vertices()).buffer();
         let vertex_buffer = context.create_buffer().unwrap();
         context.bind_buffer(gl::ARRAY_BUFFER, Some(&vertex_buffer));
@@ -38,17 +121,226 @@ trait Mesh {
         context.bind_buffer(gl::ARRAY_BUFFER, Some(&normal_buffer));
         context.buffer_data_1(gl::ARRAY_BUFFER, Some(&normals), gl::STATIC_DRAW);
 
-        let colors = TypedArray::<f32>::from(self.colors()).buffer();
+        let source_colors = self.colors_or_gray();
+        let colors = match color_format {
+            ColorFormat::Float => TypedArray::<f32>::from(&*source_colors).buffer(),
+            ColorFormat::UnsignedByte => {
+                let bytes: Vec<u8> = source_colors
+                    .iter()
+                    .map(|c| (c.max(0.).min(1.) * 255.).round() as u8)
+                    .collect();
+                TypedArray::<u8>::from(bytes.as_slice()).buffer()
+            }
+        };
         let color_buffer = context.create_buffer().unwrap();
         context.bind_buffer(gl::ARRAY_BUFFER, Some(&color_buffer));
         context.buffer_data_1(gl::ARRAY_BUFFER, Some(&colors), gl::STATIC_DRAW);
 
-        let indices = TypedArray::<u16>::from(self.indices()).buffer();
+        let alpha_buffer = self.bind_optional(context, self.alphas());
+        let uv_buffer = self.bind_optional(context, self.texcoords());
+
+        BoundMesh::new(
+            context.clone(),
+            self.primitive(),
+            (self.vertices().len() / 3) as u32,
+            VertexBuffers {
+                vertices: vertex_buffer,
+                normals: normal_buffer,
+                colors: color_buffer,
+                color_format,
+                layout: VertexLayout::Separate,
+                alphas: alpha_buffer,
+                texcoords: uv_buffer,
+            },
+            self.bind_indices(context),
+        )
+    }
+
+    /// Binds the mesh with positions, normals and (float) colors interleaved in a single
+    /// buffer, so drawing it needs one `bind_buffer` instead of three.  Meshes without normals
+    /// (like point clouds) get zero ones, and meshes without colors are gray.
+    fn bind_interleaved(&self, context: &WebGLRenderingContext) -> BoundMesh {
+        let normals = self.normals();
+        let colors = self.colors_or_gray();
+        let vertices: Vec<f32> = self
+            .vertices()
+            .chunks(3)
+            .enumerate()
+            .flat_map(|(i, v)| {
+                let n = normals.get(i * 3..i * 3 + 3).unwrap_or(&[0.; 3]);
+                v.iter().chain(n).chain(&colors[i * 3..i * 3 + 3]).cloned()
+            }).collect();
+        let vertices = TypedArray::<f32>::from(vertices.as_slice()).buffer();
+        let vertex_buffer = context.create_buffer().unwrap();
+        context.bind_buffer(gl::ARRAY_BUFFER, Some(&vertex_buffer));
+        context.buffer_data_1(gl::ARRAY_BUFFER, Some(&vertices), gl::STATIC_DRAW);
+
+        let alpha_buffer = self.bind_optional(context, self.alphas());
+        let uv_buffer = self.bind_optional(context, self.texcoords());
+
+        BoundMesh::new(
+            context.clone(),
+            self.primitive(),
+            (self.vertices().len() / 3) as u32,
+            VertexBuffers {
+                vertices: vertex_buffer.clone(),
+                normals: vertex_buffer.clone(),
+                colors: vertex_buffer,
+                color_format: ColorFormat::Float,
+                layout: VertexLayout::Interleaved,
+                alphas: alpha_buffer,
+                texcoords: uv_buffer,
+            },
+            self.bind_indices(context),
+        )
+    }
+
+    /// Uploads the triangles (as a strip, for meshes with `strip_indices`) and their edges
+    /// (see `line_indices`), as `u32`s for meshes with `wide_indices`, otherwise as `u16`s.
+    fn bind_indices(&self, context: &WebGLRenderingContext) -> IndexBuffers {
+        let wide = self.wide_indices();
+        let strip = self.strip_indices();
+        let (indices, lines, num_indices, num_lines, index_type) = if !strip.is_empty() {
+            let lines = line_indices(self.indices());
+            (
+                TypedArray::<u16>::from(strip).buffer(),
+                TypedArray::<u16>::from(lines.as_slice()).buffer(),
+                strip.len(),
+                lines.len(),
+                gl::UNSIGNED_SHORT,
+            )
+        } else if wide.is_empty() {
+            let lines = line_indices(self.indices());
+            (
+                TypedArray::<u16>::from(self.indices()).buffer(),
+                TypedArray::<u16>::from(lines.as_slice()).buffer(),
+                self.indices().len(),
+                lines.len(),
+                gl::UNSIGNED_SHORT,
+            )
+        } else {
+            let lines = line_indices(wide);
+            (
+                TypedArray::<u32>::from(wide).buffer(),
+                TypedArray::<u32>::from(lines.as_slice()).buffer(),
+                wide.len(),
+                lines.len(),
+                gl::UNSIGNED_INT,
+            )
+        };
         let index_buffer = context.create_buffer().unwrap();
         context.bind_buffer(gl::ELEMENT_ARRAY_BUFFER, Some(&index_buffer));
         context.buffer_data_1(gl::ELEMENT_ARRAY_BUFFER, Some(&indices), gl::STATIC_DRAW);
 
-        BoundMesh::new(self.indices().len() as u16, vertex_buffer, normal_buffer, color_buffer, index_buffer)
+        let line_index_buffer = context.create_buffer().unwrap();
+        context.bind_buffer(gl::ELEMENT_ARRAY_BUFFER, Some(&line_index_buffer));
+        context.buffer_data_1(gl::ELEMENT_ARRAY_BUFFER, Some(&lines), gl::STATIC_DRAW);
+
+        IndexBuffers {
+            triangles: index_buffer,
+            num_indices: num_indices as u32,
+            lines: line_index_buffer,
+            num_line_indices: num_lines as u32,
+            index_type,
+        }
+    }
+
+    /// Uploads an optional attribute (`alphas` or `texcoords`) into a buffer of its own, if the
+    /// mesh has it.  These are kept apart from the other attributes so meshes without them
+    /// need no buffer at all.
+    fn bind_optional(&self, context: &WebGLRenderingContext, data: &[f32]) -> Option<WebGLBuffer> {
+        if data.is_empty() {
+            return None;
+        }
+        let data = TypedArray::<f32>::from(data).buffer();
+        let buffer = context.create_buffer().unwrap();
+        context.bind_buffer(gl::ARRAY_BUFFER, Some(&buffer));
+        context.buffer_data_1(gl::ARRAY_BUFFER, Some(&data), gl::STATIC_DRAW);
+        Some(buffer)
+    }
+
+    /// Minimum and maximum corners of the axis-aligned box around the vertices, or `None` if
+    /// there are no vertices.
+    fn bounding_box(&self) -> Option<(Point3<f32>, Point3<f32>)> {
+        self.vertices()
+            .chunks(3)
+            .map(|v| Point3::new(v[0], v[1], v[2]))
+            .fold(None, |bounds, p| Some(bounds.map_or((p, p), |b| union_boxes(b, (p, p)))))
+    }
+
+    /// The mesh's colors, or a uniform light gray for meshes without any (like the baked
+    /// ziggurat).
+    fn colors_or_gray(&self) -> Cow<[f32]> {
+        if self.colors().is_empty() {
+            Cow::Owned(vec![0.8; self.vertices().len()])
+        } else {
+            Cow::Borrowed(self.colors())
+        }
+    }
+
+    /// Returns a copy of this mesh with every color channel multiplied by `factor`, clamped
+    /// to [0, 1].
+    fn adjust_brightness(&self, factor: f32) -> OwnedMesh {
+        OwnedMesh {
+            vertices: self.vertices().to_vec(),
+            normals: self.normals().to_vec(),
+            colors: self.colors().iter().map(|c| (c * factor).max(0.).min(1.)).collect(),
+            alphas: self.alphas().to_vec(),
+            texcoords: self.texcoords().to_vec(),
+            indices: self.indices().to_vec(),
+            wide_indices: self.wide_indices().to_vec(),
+        }
+    }
+
+    /// Returns a copy of this mesh with `gamma` applied to every color channel, so values
+    /// above 1 lift the midtones and values below 1 darken them.
+    fn adjust_gamma(&self, gamma: f32) -> OwnedMesh {
+        OwnedMesh {
+            vertices: self.vertices().to_vec(),
+            normals: self.normals().to_vec(),
+            colors: self.colors().iter().map(|c| c.max(0.).min(1.).powf(1. / gamma)).collect(),
+            alphas: self.alphas().to_vec(),
+            texcoords: self.texcoords().to_vec(),
+            indices: self.indices().to_vec(),
+            wide_indices: self.wide_indices().to_vec(),
+        }
+    }
+}
+
+/// A mesh with its own buffers, e.g. the result of processing another mesh.
+#[derive(Default)]
+struct OwnedMesh {
+    vertices: Vec<f32>,
+    normals: Vec<f32>,
+    colors: Vec<f32>,
+    alphas: Vec<f32>,
+    texcoords: Vec<f32>,
+    indices: Vec<u16>,
+    /// Used instead of `indices` when copying a mesh that has them; see `Mesh::wide_indices`.
+    wide_indices: Vec<u32>,
+}
+
+impl Mesh for OwnedMesh {
+    fn vertices(&self) -> &[f32] {
+        self.vertices.as_slice()
+    }
+    fn normals(&self) -> &[f32] {
+        self.normals.as_slice()
+    }
+    fn colors(&self) -> &[f32] {
+        self.colors.as_slice()
+    }
+    fn alphas(&self) -> &[f32] {
+        self.alphas.as_slice()
+    }
+    fn texcoords(&self) -> &[f32] {
+        self.texcoords.as_slice()
+    }
+    fn indices(&self) -> &[u16] {
+        self.indices.as_slice()
+    }
+    fn wide_indices(&self) -> &[u32] {
+        self.wide_indices.as_slice()
     }
 }
 
@@ -84,71 +376,82 @@ impl Mesh for Cube {
 
     fn indices(&self) -> &[u16] {
         &[
-            0, 1, 2, 0, 2, 3, 4, 5, 6, 4, 6, 7, 8, 9, 10, 8, 10, 11, 12, 13, 14, 12, 14, 15, 16,
-            17, 18, 16, 18, 19, 20, 21, 22, 20, 22, 23,
+            0, 2, 1, 0, 3, 2, 4, 5, 6, 4, 6, 7, 8, 10, 9, 8, 11, 10, 12, 13, 14, 12, 14, 15, 16,
+            18, 17, 16, 19, 18, 20, 21, 22, 20, 22, 23,
         ]
     }
 }
 
-struct PlyMesh {
+/// A stepped pyramid of `tiers` square boxes standing on the origin, the bottom one
+/// `base_size` wide and each one above `tier_ratio` times the width of the one below.  Tiers are
+/// shaded from dark at the base to light at the top.
+struct Ziggurat {
+    pub tiers: u32,
+    pub base_size: f32,
+    pub tier_ratio: f32,
     vertices: Vec<f32>,
     normals: Vec<f32>,
     colors: Vec<f32>,
     indices: Vec<u16>,
 }
 
-impl PlyMesh {
-    pub fn parse<T: Read>(source: &mut T) -> Self {
-        let parser = ply_rs::parser::Parser::<ply_rs::ply::DefaultElement>::new();
-        let ply = parser.read_ply(source).unwrap();
-        let vertices: Vec<f32> = ply.payload.get("vertex").unwrap()
-            .iter()
-            .flat_map(|x| match (x.get("x"), x.get("y"), x.get("z")) {
-                (Some(ply_rs::ply::Property::Float(x)), Some(ply_rs::ply::Property::Float(y)), Some(ply_rs::ply::Property::Float(z))) => vec![x, y, z],
-                _ => {
-                    console!(log, "Something else");
-                    vec![]
-                }
-            }).cloned()
-            .collect();
-        let normals: Vec<f32> = ply.payload.get("vertex").unwrap()
-            .iter()
-            .flat_map(|x| match (x.get("nx"), x.get("ny"), x.get("nz")) {
-                (Some(ply_rs::ply::Property::Float(x)), Some(ply_rs::ply::Property::Float(y)), Some(ply_rs::ply::Property::Float(z))) => vec![x, y, z],
-                _ => {
-                    console!(log, "Something else");
-                    vec![]
-                }
-            }).cloned()
-            .collect();
-        let colors: Vec<f32> = ply.payload.get("vertex").unwrap()
-            .iter()
-            .flat_map(|x| match (x.get("red"), x.get("green"), x.get("blue")) {
-                (Some(ply_rs::ply::Property::UChar(r)), Some(ply_rs::ply::Property::UChar(g)), Some(ply_rs::ply::Property::UChar(b))) => vec![r, g, b],
-                _ => {
-                    console!(log, "Something else");
-                    vec![]
-                }
-            }).map(|x| (*x as f32) / 255.)
-            .collect();
-        let indices: Vec<u16> = ply
-            .payload
-            .get("face")
-            .unwrap()
-            .iter()
-            .filter_map(|x| match x.get("vertex_indices") {
-                Some(ply_rs::ply::Property::ListUInt(x)) => Some(x),
-                _ => {
-                    console!(log, "Something else");
-                    None
+impl Ziggurat {
+    pub fn new(tiers: u32, base_size: f32, tier_ratio: f32) -> Self {
+        let mut ziggurat = Ziggurat {
+            tiers,
+            base_size,
+            tier_ratio,
+            vertices: Vec::new(),
+            normals: Vec::new(),
+            colors: Vec::new(),
+            indices: Vec::new(),
+        };
+        let (x, y, z) = (Vector3::unit_x(), Vector3::unit_y(), Vector3::unit_z());
+        // Each face's outward normal and two in-plane axes with u x v = normal, so that
+        // corners visited in u/v order wind counter-clockwise seen from outside.
+        let faces = [
+            (y, z, x),
+            (-y, x, z),
+            (x, y, z),
+            (-x, z, y),
+            (z, x, y),
+            (-z, y, x),
+        ];
+
+        let tier_height = base_size / (2 * tiers.max(1)) as f32;
+        let (base_color, top_color) = (vec3(0.35, 0.22, 0.12), vec3(0.95, 0.8, 0.55));
+        for tier in 0..tiers {
+            let half_width = 0.5 * base_size * tier_ratio.powi(tier as i32);
+            let half = vec3(half_width, 0.5 * tier_height, half_width);
+            let center = vec3(0., (tier as f32 + 0.5) * tier_height, 0.);
+            let t = tier as f32 / (tiers - 1).max(1) as f32;
+            let color = base_color + (top_color - base_color) * t;
+
+            for &(normal, u, v) in &faces {
+                let first = (ziggurat.vertices.len() / 3) as u16;
+                let face_center = center + normal.mul_element_wise(half);
+                let (u, v) = (u.mul_element_wise(half), v.mul_element_wise(half));
+                for corner in &[-u - v, u - v, u + v, v - u] {
+                    let p = face_center + *corner;
+                    ziggurat.vertices.extend_from_slice(&[p.x, p.y, p.z]);
+                    ziggurat.normals.extend_from_slice(&[normal.x, normal.y, normal.z]);
+                    ziggurat.colors.extend_from_slice(&[color.x, color.y, color.z]);
                 }
-            }).flat_map(|x| x.iter().map(|x| *x as u16))
-            .collect();
-        PlyMesh { vertices, normals, colors, indices }
+                ziggurat.indices.extend_from_slice(&[
+                    first,
+                    first + 1,
+                    first + 2,
+                    first,
+                    first + 2,
+                    first + 3,
+                ]);
+            }
+        }
+        ziggurat
     }
 }
 
-impl Mesh for PlyMesh {
+impl Mesh for Ziggurat {
     fn vertices(&self) -> &[f32] {
         self.vertices.as_slice()
     }
@@ -163,284 +466,5464 @@ impl Mesh for PlyMesh {
     }
 }
 
-struct BoundMesh {
-    pub num_indices: u16,
-    pub vertex_buffer: WebGLBuffer,
-    pub normal_buffer: WebGLBuffer,
-    pub color_buffer: WebGLBuffer,
-    pub index_buffer: WebGLBuffer,
+/// A flat square grid in the XZ plane spanning [-1, 1] on each axis, facing up, split into
+/// `subdivisions` x `subdivisions` cells.  Indices are `u16`, so the `(subdivisions + 1)^2`
+/// vertices limit `subdivisions` to 255.
+struct Plane {
+    pub subdivisions: u32,
+    /// Draw the plane as one triangle strip, with about a third as many indices as separate
+    /// triangles; see `Mesh::strip_indices`.  Off by default.
+    pub triangle_strip: bool,
+    vertices: Vec<f32>,
+    normals: Vec<f32>,
+    colors: Vec<f32>,
+    /// Stretching the whole of [0, 1] x [0, 1] across the plane.
+    texcoords: Vec<f32>,
+    indices: Vec<u16>,
+    /// The strip drawn when `triangle_strip` is set.
+    strip_indices: Vec<u16>,
 }
 
-impl BoundMesh {
-    pub fn new(
-        num_indices: u16,
-        vertex_buffer: WebGLBuffer,
-        normal_buffer: WebGLBuffer,
-        color_buffer: WebGLBuffer,
-        index_buffer: WebGLBuffer,
-    ) -> Self {
-        BoundMesh {
-            num_indices,
-            vertex_buffer,
-            normal_buffer,
-            color_buffer,
-            index_buffer,
+impl Plane {
+    pub fn new(subdivisions: u32) -> Self {
+        assert!(
+            subdivisions >= 1 && subdivisions <= 255,
+            "Plane needs 1-255 subdivisions to index with u16"
+        );
+        let n = subdivisions;
+        let count = ((n + 1) * (n + 1)) as usize;
+        let mut plane = Plane {
+            subdivisions,
+            triangle_strip: false,
+            vertices: Vec::with_capacity(count * 3),
+            normals: Vec::with_capacity(count * 3),
+            colors: Vec::with_capacity(count * 3),
+            texcoords: Vec::with_capacity(count * 2),
+            indices: Vec::with_capacity((n * n * 6) as usize),
+            strip_indices: Vec::with_capacity((n * (2 * n + 4) - 2) as usize),
+        };
+        for row in 0..=n {
+            for column in 0..=n {
+                let x = column as f32 / n as f32 * 2. - 1.;
+                let z = row as f32 / n as f32 * 2. - 1.;
+                plane.vertices.extend_from_slice(&[x, 0., z]);
+                plane.normals.extend_from_slice(&[0., 1., 0.]);
+                plane.colors.extend_from_slice(&[0.45, 0.5, 0.4]);
+                plane
+                    .texcoords
+                    .extend_from_slice(&[column as f32 / n as f32, row as f32 / n as f32]);
+            }
+        }
+        for row in 0..n {
+            for column in 0..n {
+                // Counter-clockwise seen from above.
+                let i = (row * (n + 1) + column) as u16;
+                let j = i + (n + 1) as u16;
+                plane.indices.extend_from_slice(&[i, j, i + 1, i + 1, j, j + 1]);
+            }
         }
+        // Each row zigzags between its two edges, giving the same triangles in the same order
+        // and winding.  Repeating the last vertex of one row and the first of the next joins
+        // them with degenerate triangles, keeping the next row's first triangle at an even
+        // position so its winding isn't flipped.
+        for row in 0..n {
+            let first = (row * (n + 1)) as u16;
+            if row > 0 {
+                let last = *plane.strip_indices.last().unwrap();
+                plane.strip_indices.extend_from_slice(&[last, first]);
+            }
+            for column in 0..=n {
+                let i = first + column as u16;
+                let j = i + (n + 1) as u16;
+                plane.strip_indices.extend_from_slice(&[i, j]);
+            }
+        }
+        plane
     }
 }
 
-struct Shader {
-    pub program: WebGLProgram,
+impl Mesh for Plane {
+    fn vertices(&self) -> &[f32] {
+        self.vertices.as_slice()
+    }
+    fn normals(&self) -> &[f32] {
+        self.normals.as_slice()
+    }
+    fn colors(&self) -> &[f32] {
+        self.colors.as_slice()
+    }
+    fn texcoords(&self) -> &[f32] {
+        self.texcoords.as_slice()
+    }
+    fn indices(&self) -> &[u16] {
+        self.indices.as_slice()
+    }
+    fn strip_indices(&self) -> &[u16] {
+        if self.triangle_strip {
+            self.strip_indices.as_slice()
+        } else {
+            &[]
+        }
+    }
 }
 
-impl Shader {
-    pub fn new(context: &WebGLRenderingContext, vertex_code: &str, fragment_code: &str) -> Self {
-        let vertex_shader = context.create_shader(gl::VERTEX_SHADER).unwrap();
-        context.shader_source(&vertex_shader, vertex_code);
-        context.compile_shader(&vertex_shader);
-        console!(log, context.get_shader_info_log(&vertex_shader));
-
-        let fragment_shader = context.create_shader(gl::FRAGMENT_SHADER).unwrap();
-        context.shader_source(&fragment_shader, fragment_code);
-        context.compile_shader(&fragment_shader);
-        console!(log, context.get_shader_info_log(&fragment_shader));
+/// A reference grid on the XZ plane: lines `spacing` apart, out to `extent` either side of
+/// the axes.  Every `major_every`th line, counting out from the axes, is drawn brighter to
+/// help read scale; 0 makes them all minor.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Grid {
+    pub spacing: f32,
+    pub extent: f32,
+    pub major_every: u32,
+}
 
-        let program = context.create_program().unwrap();
-        context.attach_shader(&program, &vertex_shader);
-        context.attach_shader(&program, &fragment_shader);
-        context.link_program(&program);
-        console!(log, context.get_program_info_log(&program));
+/// A `Grid` has at most this many lines either side of each axis, keeping its indices within
+/// `u16`.
+const MAX_GRID_STEPS: u32 = 1000;
 
-        Shader { program }
+/// Line segments, for drawing with `gl::LINES`, along the world axes from the origin out to
+/// `size`: X in red, Y in green and Z in blue.  With a `grid`, its lines are grey.
+fn axis_gizmo(size: f32, grid: Option<Grid>) -> OwnedMesh {
+    let mut mesh = OwnedMesh::default();
+    {
+        let mut line = |from: [f32; 3], to: [f32; 3], color: [f32; 3]| {
+            let i = (mesh.vertices.len() / 3) as u16;
+            mesh.vertices.extend_from_slice(&from);
+            mesh.vertices.extend_from_slice(&to);
+            for _ in 0..2 {
+                mesh.normals.extend_from_slice(&[0., 1., 0.]);
+                mesh.colors.extend_from_slice(&color);
+            }
+            mesh.indices.extend_from_slice(&[i, i + 1]);
+        };
+        // The grid goes first so the axes, drawn later at the same depth, cover it.
+        if let Some(grid) = grid {
+            let steps = ((grid.extent / grid.spacing) as u32).min(MAX_GRID_STEPS) as i32;
+            let extent = grid.extent;
+            for k in -steps..=steps {
+                let t = k as f32 * grid.spacing;
+                let major = grid.major_every > 0 && k % grid.major_every as i32 == 0;
+                let shade = if major { 0.6 } else { 0.3 };
+                line([t, 0., -extent], [t, 0., extent], [shade; 3]);
+                line([-extent, 0., t], [extent, 0., t], [shade; 3]);
+            }
+        }
+        line([0., 0., 0.], [size, 0., 0.], [1., 0., 0.]);
+        line([0., 0., 0.], [0., size, 0.], [0., 1., 0.]);
+        line([0., 0., 0.], [0., 0., size], [0., 0., 1.]);
     }
+    mesh
 }
 
-bitflags! {
-    struct Keys: u8 {
-        const UP    = 0b0000_0001;
-        const DOWN  = 0b0000_0010;
-        const LEFT  = 0b0000_0100;
-        const RIGHT = 0b0000_1000;
-    }
+/// The smallest box containing both `(min, max)` boxes.
+fn union_boxes(
+    (a_min, a_max): (Point3<f32>, Point3<f32>),
+    (b_min, b_max): (Point3<f32>, Point3<f32>),
+) -> (Point3<f32>, Point3<f32>) {
+    (
+        Point3::new(a_min.x.min(b_min.x), a_min.y.min(b_min.y), a_min.z.min(b_min.z)),
+        Point3::new(a_max.x.max(b_max.x), a_max.y.max(b_max.y), a_max.z.max(b_max.z)),
+    )
 }
 
-struct State {
-    time_old: f64,
-    mov_matrix: Matrix4<f32>,
-    view_matrix: Matrix4<f32>,
-    canvas: CanvasElement,
-    context: WebGLRenderingContext,
-    shader: Shader,
-    position: u32,
-    normal: u32,
-    color: u32,
-    p_matrix: WebGLUniformLocation,
-    v_matrix: WebGLUniformLocation,
-    m_matrix: WebGLUniformLocation,
-    ziggurat: BoundMesh,
-    peon: BoundMesh,
-    keys: Keys,
-    prev_keys: Keys,
+/// Summary statistics for checking incoming assets.
+#[derive(Debug)]
+struct MeshReport {
+    pub vertex_count: usize,
+    pub triangle_count: usize,
+    /// Minimum and maximum corners, or `None` if there are no vertices.
+    pub bounding_box: Option<(Point3<f32>, Point3<f32>)>,
+    pub has_normals: bool,
+    /// Whether every normal has length 1 (to within a small tolerance).
+    pub normals_unit_length: bool,
+    pub has_colors: bool,
+    /// Triangles with (near) zero area.
+    pub degenerate_triangles: usize,
+    /// Vertices whose position exactly matches an earlier vertex's.
+    pub duplicate_vertices: usize,
 }
 
-impl State {
-    fn animate(&mut self, time: f64, rc: Rc<RefCell<Self>>) {
-        let dt = (time - self.time_old) as f32;
-        self.mov_matrix = self.mov_matrix * Matrix4::<f32>::from(Euler::new(
-            Rad(dt
-                * 0.001
-                * (self.keys.contains(Keys::UP) as i8 - self.keys.contains(Keys::DOWN) as i8)
-                    as f32),
-            Rad(dt
-                * 0.001
-                * (self.keys.contains(Keys::RIGHT) as i8 - self.keys.contains(Keys::LEFT) as i8)
-                    as f32),
-            Rad(0.),
-        ));
-        self.time_old = time;
-
-        self.context.enable(gl::DEPTH_TEST);
-        self.context.depth_func(gl::LEQUAL);
-        self.context.clear_color(0.0, 0.0, 0.0, 1.0);
-        self.context.clear_depth(1.0);
-        self.context.cull_face(gl::FRONT_AND_BACK);
+fn analyze(mesh: &dyn Mesh) -> MeshReport {
+    let vertices = mesh.vertices();
+    let position = |index: usize| {
+        Point3::new(vertices[index * 3], vertices[index * 3 + 1], vertices[index * 3 + 2])
+    };
+    let vertex_count = vertices.len() / 3;
 
-        let (w, h) = (self.canvas.width(), self.canvas.height());
-        let proj_matrix = PerspectiveFov {
-            fovy: Deg(80.).into(),
-            aspect: (w as f32) / (h as f32),
-            near: 1.,
-            far: 100.,
-        };
-        let proj_matrix: Matrix4<f32> = proj_matrix.into();
+    let normals = mesh.normals();
+    let normals_unit_length = normals
+        .chunks(3)
+        .all(|n| (vec3(n[0], n[1], n[2]).magnitude() - 1.).abs() < 1e-3);
 
-        self.context.viewport(0, 0, w as i32, h as i32);
-        self.context
-            .clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+    let degenerate_triangles = mesh
+        .triangles()
+        .filter(|triangle| {
+            let a = position(triangle[0] as usize);
+            let b = position(triangle[1] as usize);
+            let c = position(triangle[2] as usize);
+            (b - a).cross(c - a).magnitude2() < 1e-12
+        }).count();
 
-        self.context.use_program(Some(&self.shader.program));
-        self.context.enable_vertex_attrib_array(self.position);
-        self.context.enable_vertex_attrib_array(self.color);
-        self.context.enable_vertex_attrib_array(self.normal);
+    let mut seen = HashSet::new();
+    let duplicate_vertices = vertices
+        .chunks(3)
+        .filter(|v| !seen.insert((v[0].to_bits(), v[1].to_bits(), v[2].to_bits())))
+        .count();
 
-        self.context.bind_buffer(gl::ARRAY_BUFFER, Some(&self.peon.vertex_buffer));
-        self.context.vertex_attrib_pointer(self.position, 3, gl::FLOAT, false, 0, 0);
+    MeshReport {
+        vertex_count,
+        triangle_count: mesh.triangles().count(),
+        bounding_box: mesh.bounding_box(),
+        has_normals: !normals.is_empty(),
+        normals_unit_length: !normals.is_empty() && normals_unit_length,
+        has_colors: !mesh.colors().is_empty(),
+        degenerate_triangles,
+        duplicate_vertices,
+    }
+}
 
-        self.context.bind_buffer(gl::ARRAY_BUFFER, Some(&self.peon.color_buffer));
-        self.context.vertex_attrib_pointer(self.color, 3, gl::FLOAT, false, 0, 0);
+/// Where a ray hits a mesh.
+#[derive(Clone, Copy, Debug)]
+struct RayHit {
+    /// Distance along the ray.
+    t: f32,
+    /// Index of the triangle that was hit, in units of three indices.
+    triangle: usize,
+    /// Barycentric coordinates of the hit relative to the triangle's second and third vertices.
+    u: f32,
+    v: f32,
+}
 
-        self.context.bind_buffer(gl::ARRAY_BUFFER, Some(&self.peon.normal_buffer));
-        self.context.vertex_attrib_pointer(self.normal, 3, gl::FLOAT, false, 0, 0);
+impl RayHit {
+    /// Interpolates the mesh's per-vertex colors at the hit point.
+    fn color(&self, mesh: &dyn Mesh) -> Option<Vector3<f32>> {
+        let colors = mesh.colors();
+        let corners = mesh.triangles().nth(self.triangle)?;
+        let color = |index: u32| {
+            let i = index as usize * 3;
+            colors.get(i..i + 3).map(|c| vec3(c[0], c[1], c[2]))
+        };
+        let (a, b, c) = (color(corners[0])?, color(corners[1])?, color(corners[2])?);
+        Some(a * (1. - self.u - self.v) + b * self.u + c * self.v)
+    }
+}
 
-        self.context.uniform_matrix4fv(
-            Some(&self.p_matrix),
-            false,
-            &(proj_matrix.as_ref() as &[f32; 16])[..],
-        );
-        self.context.uniform_matrix4fv(
-            Some(&self.v_matrix),
-            false,
-            &(self.view_matrix.as_ref() as &[f32; 16])[..],
-        );
-        self.context.uniform_matrix4fv(
-            Some(&self.m_matrix),
-            false,
-            &(self.mov_matrix.as_ref() as &[f32; 16])[..],
-        );
+/// Finds the nearest triangle of `mesh` that the ray hits, if any.
+fn intersect_ray(mesh: &dyn Mesh, origin: Point3<f32>, direction: Vector3<f32>) -> Option<RayHit> {
+    let vertices = mesh.vertices();
+    let vertex = |index: u32| {
+        let i = index as usize * 3;
+        Point3::new(vertices[i], vertices[i + 1], vertices[i + 2])
+    };
+    mesh.triangles()
+        .enumerate()
+        .filter_map(|(index, triangle)| {
+            // Möller-Trumbore ray/triangle intersection.
+            let a = vertex(triangle[0]);
+            let edge1 = vertex(triangle[1]) - a;
+            let edge2 = vertex(triangle[2]) - a;
+            let p = direction.cross(edge2);
+            let det = edge1.dot(p);
+            if det.abs() < 1e-7 {
+                return None;
+            }
+            let s = origin - a;
+            let u = s.dot(p) / det;
+            if u < 0. || u > 1. {
+                return None;
+            }
+            let q = s.cross(edge1);
+            let v = direction.dot(q) / det;
+            if v < 0. || u + v > 1. {
+                return None;
+            }
+            let t = edge2.dot(q) / det;
+            if t > 0. {
+                Some(RayHit { t, triangle: index, u, v })
+            } else {
+                None
+            }
+        }).fold(None, |nearest: Option<RayHit>, hit| match nearest {
+            Some(nearest) if nearest.t <= hit.t => Some(nearest),
+            _ => Some(hit),
+        })
+}
 
-        self.context
-            .bind_buffer(gl::ELEMENT_ARRAY_BUFFER, Some(&self.peon.index_buffer));
-        self.context
-            .draw_elements(gl::TRIANGLES, self.peon.num_indices as i32, gl::UNSIGNED_SHORT, 0);
-
-//        self.context.bind_buffer(gl::ARRAY_BUFFER, Some(&self.ziggurat.vertex_buffer));
-//        self.context.vertex_attrib_pointer(self.position, 3, gl::FLOAT, false, 0, 0);
-//
-//        self.context.bind_buffer(gl::ARRAY_BUFFER, Some(&self.ziggurat.color_buffer));
-//        self.context.vertex_attrib_pointer(self.color, 3, gl::FLOAT, false, 0, 0);
-//
-//        self.context.bind_buffer(gl::ARRAY_BUFFER, Some(&self.ziggurat.normal_buffer));
-//        self.context.vertex_attrib_pointer(self.normal, 3, gl::FLOAT, false, 0, 0);
-//
-//        self.context.uniform_matrix4fv(
-//            Some(&self.p_matrix),
-//            false,
-//            &(proj_matrix.as_ref() as &[f32; 16])[..],
-//        );
-//        self.context.uniform_matrix4fv(
-//            Some(&self.v_matrix),
-//            false,
-//            &(self.view_matrix.as_ref() as &[f32; 16])[..],
-//        );
-//        self.context.uniform_matrix4fv(
-//            Some(&self.m_matrix),
-//            false,
-//            &(self.mov_matrix.as_ref() as &[f32; 16])[..],
-//        );
-//
-//        self.context
-//            .bind_buffer(gl::ELEMENT_ARRAY_BUFFER, Some(&self.ziggurat.index_buffer));
-//        self.context
-//            .draw_elements(gl::TRIANGLES, self.ziggurat.num_indices as i32, gl::UNSIGNED_SHORT, 0);
+#[derive(Debug)]
+enum PlyMeshError {
+    /// The source could not be read.
+    Io(io::Error),
+    /// The source contained nothing but (possibly) whitespace.
+    Empty,
+    /// A required element (e.g. `vertex`) isn't declared.
+    MissingElement(String),
+    /// An element is missing a property we need, e.g. a vertex without `z`.
+    MissingProperty { element: String, property: String },
+    /// A property has a type we can't use, e.g. a list where a coordinate should be.
+    UnexpectedProperty { element: String, property: String },
+    /// A face of `element` refers to vertex `index`, past the end of the vertex list.
+    IndexOutOfRange { element: String, index: u32 },
+    /// The vertices have no `red`/`green`/`blue` colors, and `ParseOptions::require_colors`
+    /// is set.
+    MissingColors,
+    /// The request for the data failed with this HTTP status, or 0 if there was no response
+    /// at all (e.g. a network error).
+    Http(u16),
+    /// The mesh has more vertices than 16-bit indices can address, so it had to be split into
+    /// this many chunks, but the caller needs a single mesh.
+    TooManyVertices(usize),
+}
 
-        window().request_animation_frame(move |time| {
-            rc.borrow_mut().animate(time, rc.clone());
-        });
-        self.prev_keys = self.keys;
+impl fmt::Display for PlyMeshError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PlyMeshError::Io(ref err) => write!(f, "couldn't read PLY data: {}", err),
+            PlyMeshError::Empty => write!(f, "PLY data is empty"),
+            PlyMeshError::MissingElement(ref element) => {
+                write!(f, "PLY data has no \"{}\" element", element)
+            }
+            PlyMeshError::MissingProperty {
+                ref element,
+                ref property,
+            } => write!(f, "\"{}\" element is missing \"{}\"", element, property),
+            PlyMeshError::UnexpectedProperty {
+                ref element,
+                ref property,
+            } => write!(
+                f,
+                "\"{}\" property of \"{}\" has an unexpected type",
+                property, element
+            ),
+            PlyMeshError::IndexOutOfRange { ref element, index } => write!(
+                f,
+                "\"{}\" element refers to vertex {}, which doesn't exist",
+                element, index
+            ),
+            PlyMeshError::MissingColors => write!(f, "PLY vertices have no colors"),
+            PlyMeshError::Http(0) => write!(f, "couldn't fetch PLY data: no response"),
+            PlyMeshError::Http(status) => write!(f, "couldn't fetch PLY data: HTTP {}", status),
+            PlyMeshError::TooManyVertices(chunks) => write!(
+                f,
+                "PLY mesh has more than {} vertices ({} chunks' worth)",
+                MAX_CHUNK_VERTICES, chunks
+            ),
+        }
     }
 }
 
-fn main() {
-    stdweb::initialize();
+impl Error for PlyMeshError {}
 
-    // Doesn't work because web-sys isn't integrated with stdweb.
-    //let audio = web_sys::AudioContext::new().unwrap();
-    //let source = audio.create_buffer_source().unwrap(); // creates a sound source
-    ////source.buffer = buffer;                    // tell the source which sound to play
-    //source.connect_with_audio_node(&audio.destination());       // connect the source to the context's destination (the speakers)
-    //source.start();
+impl From<io::Error> for PlyMeshError {
+    fn from(err: io::Error) -> Self {
+        PlyMeshError::Io(err)
+    }
+}
 
-    // Doesn't work due to https://github.com/koute/stdweb/issues/171
-    //let image = ImageElement::new();
-    //image.add_event_listener({
-    //    let image = image.clone();
-    //    move |evt: ResourceLoadEvent| {
-    //        console!(log, "Image loaded");
-    //        console!(log, "Image loaded", image.get_attribute("src"));
-    //    }
-    //});
-    //image.add_event_listener({
-    //    let image = image.clone();
-    //    move |_: ResourceErrorEvent| {
-    //        console!(log, "Image failed to load");
-    //        console!(log, "Image failed to load", image.get_attribute("src"));
-    //    }
-    //});
-    //image.add_event_listener({
-    //    let image = image.clone();
-    //    move |_: ResourceAbortEvent| {
-    //        console!(log, "Image load aborted");
-    //        console!(log, "Image load aborted", image.get_attribute("src"));
-    //    }
-    //});
-    //image.set_src("test.png");
+/// Meshes with coordinates beyond this magnitude are far enough from the origin for f32
+/// precision loss to show up as jitter once transformed.
+const LARGE_COORDINATE_THRESHOLD: f32 = 10_000.;
 
-    let canvas: CanvasElement = document()
-        .query_selector("#canvas")
-        .unwrap()
-        .unwrap()
-        .try_into()
-        .unwrap();
-    let context: WebGLRenderingContext = canvas.get_context().unwrap();
+/// The most vertices a mesh can have and still be indexed with `u16`s.  Larger meshes are
+/// split into chunks of at most this many; see `PlyMesh::parse_chunks_with_options`.
+const MAX_CHUNK_VERTICES: usize = 1 << 16;
 
-    canvas.set_width(canvas.offset_width() as u32);
-    canvas.set_height(canvas.offset_height() as u32);
+/// How many faces to check when looking for inconsistent winding.
+const WINDING_SAMPLE_COUNT: usize = 64;
 
-    window().add_event_listener({
-        let canvas = canvas.clone();
-        move |_: ResizeEvent| {
-            canvas.set_width(canvas.offset_width() as u32);
-            canvas.set_height(canvas.offset_height() as u32);
-        }
-    });
+#[derive(Clone, Copy)]
+struct ParseOptions {
+    /// Recenter the mesh on its centroid if any coordinate exceeds
+    /// `LARGE_COORDINATE_THRESHOLD`, as with geo-referenced scans.
+    pub recenter_large_coords: bool,
+    /// Fail with `PlyMeshError::MissingColors` if the vertices have no colors, rather than
+    /// loading an uncolored mesh.
+    pub require_colors: bool,
+    /// Rescale every normal to unit length, since some exporters write them slightly (or
+    /// wildly) off, making lighting too bright or too dark.
+    pub renormalize_normals: bool,
+    /// Warn when more than this fraction of a sample of faces wind against their vertex
+    /// normals, which usually means the model will render inside-out.  `None` skips the check.
+    pub winding_warning_threshold: Option<f32>,
+    /// Reverse every face that winds against its vertex normals.
+    pub fix_winding: bool,
+    /// Keep meshes with more than `MAX_CHUNK_VERTICES` vertices whole, indexed with `u32`s
+    /// (see `Mesh::wide_indices`), rather than splitting them.  Only set this if the context
+    /// has `OES_element_index_uint`.
+    pub wide_indices: bool,
+    /// Give every face its own vertices and face normal (see `PlyMesh::to_flat_shaded`), for
+    /// a faceted look instead of smooth shading.
+    pub flat_shading: bool,
+    /// Recenter the mesh and scale it to fit a unit cube; see `PlyMesh::normalize`.
+    pub normalize: bool,
+}
 
-    let peon_ply = include_str!("../models/peon.ply");
-    let peon = PlyMesh::parse(&mut peon_ply.as_bytes()).bind(&context);
+impl Default for ParseOptions {
+    fn default() -> Self {
+        ParseOptions {
+            recenter_large_coords: true,
+            require_colors: true,
+            renormalize_normals: true,
+            winding_warning_threshold: Some(0.25),
+            fix_winding: false,
+            wide_indices: false,
+            flat_shading: false,
+            normalize: false,
+        }
+    }
+}
 
-    let ziggurat_ply = include_str!("../models/ziggurat.ply");
-    let ziggurat = PlyMesh::parse(&mut ziggurat_ply.as_bytes()).bind(&context);
+struct PlyMesh {
+    vertices: Vec<f32>,
+    normals: Vec<f32>,
+    colors: Vec<f32>,
+    /// From the vertices' optional `alpha` property; empty if they have none.
+    alphas: Vec<f32>,
+    /// From the vertices' optional `u`/`v` (or `s`/`t`) properties; empty if they have none.
+    texcoords: Vec<f32>,
+    indices: Vec<u16>,
+    /// Used instead of `indices` by meshes too big for them; see `ParseOptions::wide_indices`.
+    wide_indices: Vec<u32>,
+    /// Offset subtracted from the vertices when loaded; add it back (after undoing
+    /// `origin_scale`) to get the original coordinates.
+    pub origin_offset: Vector3<f64>,
+    /// Factor the vertices were divided by when loaded, by `normalize`; 1 otherwise.
+    pub origin_scale: f64,
+}
+
+type Ply = ply_rs::ply::Ply<ply_rs::ply::DefaultElement>;
+
+/// One geometry part of a multi-part PLY file, or one chunk of a part that had to be split.
+struct PlyPart {
+    /// The suffix shared by the part's vertex and face elements, e.g. `hull` for
+    /// `vertex_hull`/`face_hull`, or `material<N>` for parts split by material.  Empty for the
+    /// plain `vertex`/`face` pair.  Chunks of the same part share its name.
+    pub name: String,
+    pub mesh: PlyMesh,
+    /// The diffuse color of the part's material, for `BoundMesh::material_color`.
+    pub material_color: Option<Vector3<f32>>,
+}
+
+impl PlyMesh {
+    pub fn parse<T: Read>(source: &mut T) -> Result<Self, PlyMeshError> {
+        Self::parse_with_options(source, &ParseOptions::default())
+    }
+
+    pub fn parse_with_options<T: Read>(
+        source: &mut T,
+        options: &ParseOptions,
+    ) -> Result<Self, PlyMeshError> {
+        Self::single(Self::parse_chunks_with_options(source, options)?)
+    }
+
+    /// Like `parse_with_options`, but meshes with more than `MAX_CHUNK_VERTICES` vertices
+    /// come back split into several chunks (rather than as an error), each small enough to
+    /// index with `u16`.  Vertices shared by triangles in different chunks are duplicated.
+    pub fn parse_chunks_with_options<T: Read>(
+        source: &mut T,
+        options: &ParseOptions,
+    ) -> Result<Vec<Self>, PlyMeshError> {
+        let ply = Self::read(source)?;
+        let mut chunks = Self::chunks_from_elements(&ply, "vertex", "face", options)?;
+        Self::place_all(&mut chunks, options);
+        Ok(chunks)
+    }
+
+    /// Recenters and normalizes the meshes as `options` asks, all together so that they still
+    /// line up afterwards.
+    fn place_all(meshes: &mut [PlyMesh], options: &ParseOptions) {
+        if options.recenter_large_coords
+            && meshes
+                .iter()
+                .flat_map(|mesh| mesh.vertices.iter())
+                .any(|c| c.abs() > LARGE_COORDINATE_THRESHOLD)
+        {
+            Self::recenter_all(meshes);
+        }
+        if options.normalize {
+            Self::normalize_all(meshes);
+        }
+    }
+
+    /// Unwraps the only chunk of a mesh, failing if it needed splitting.
+    fn single(mut chunks: Vec<Self>) -> Result<Self, PlyMeshError> {
+        if chunks.len() > 1 {
+            return Err(PlyMeshError::TooManyVertices(chunks.len()));
+        }
+        Ok(chunks.pop().expect("chunks_from_elements always returns a chunk"))
+    }
+
+    /// Moves the mesh so its centroid is at the origin, accumulating the translation in
+    /// `origin_offset`.
+    pub fn recenter(&mut self) {
+        Self::recenter_all(slice::from_mut(self));
+    }
+
+    /// Moves the meshes by the same amount, so that the centroid of all their vertices is at
+    /// the origin, accumulating the translation in each one's `origin_offset`.
+    fn recenter_all(meshes: &mut [PlyMesh]) {
+        let count = meshes.iter().map(|mesh| mesh.vertices.len() / 3).sum::<usize>();
+        let count = count.max(1) as f64;
+        let mut centroid = Vector3::new(0f64, 0., 0.);
+        for v in meshes.iter().flat_map(|mesh| mesh.vertices.chunks(3)) {
+            centroid += Vector3::new(v[0] as f64, v[1] as f64, v[2] as f64) / count;
+        }
+        for mesh in meshes {
+            for v in mesh.vertices.chunks_mut(3) {
+                v[0] = (v[0] as f64 - centroid.x) as f32;
+                v[1] = (v[1] as f64 - centroid.y) as f32;
+                v[2] = (v[2] as f64 - centroid.z) as f32;
+            }
+            mesh.origin_offset += centroid * mesh.origin_scale;
+        }
+    }
+
+    /// Moves the mesh so its bounding box is centered on the origin, and scales it uniformly
+    /// so that its longest side is 1, i.e. it fits within ±0.5 on every axis.  Uniform scaling
+    /// leaves the normals' directions alone, so they need no adjustment.
+    pub fn normalize(&mut self) {
+        Self::normalize_all(slice::from_mut(self));
+    }
+
+    /// Normalizes the meshes (see `normalize`) by the same amount, as if they were one.
+    fn normalize_all(meshes: &mut [PlyMesh]) {
+        let bounds = meshes
+            .iter()
+            .filter_map(|mesh| mesh.bounding_box())
+            .fold(None, |bounds, b| Some(bounds.map_or(b, |a| union_boxes(a, b))));
+        let (min, max) = match bounds {
+            Some(bounds) => bounds,
+            None => return,
+        };
+        let size = max - min;
+        let longest = size.x.max(size.y).max(size.z);
+        let scale = if longest > 0. { longest } else { 1. };
+        let center = min + size / 2.;
+        for mesh in meshes {
+            for v in mesh.vertices.chunks_mut(3) {
+                v[0] = (v[0] - center.x) / scale;
+                v[1] = (v[1] - center.y) / scale;
+                v[2] = (v[2] - center.z) / scale;
+            }
+            let center = center.to_vec().cast::<f64>().unwrap();
+            mesh.origin_offset += center * mesh.origin_scale;
+            mesh.origin_scale *= scale as f64;
+        }
+    }
+
+    /// Parses every geometry part of a PLY file, in header order, each split by material
+    /// (see `split_materials`) and then into chunks as by `parse_chunks_with_options` if need
+    /// be.  Exporters that write several parts name each pair of vertex and face elements
+    /// with a common suffix (`vertex_hull`, `face_hull`, ...); suffixed vertex elements
+    /// without a matching face element are skipped, but a plain `vertex` element without
+    /// faces is still a point cloud.
+    pub fn parse_parts<T: Read>(
+        source: &mut T,
+        options: &ParseOptions,
+    ) -> Result<Vec<PlyPart>, PlyMeshError> {
+        let ply = Self::read(source)?;
+        // Materials can supply all the color, so vertex colors are optional with them.
+        let options = &ParseOptions {
+            require_colors: options.require_colors && !ply.payload.contains_key("material"),
+            ..*options
+        };
+        // Kept apart from the meshes until they've been placed together.
+        let mut labels = Vec::new();
+        let mut meshes = Vec::new();
+        for vertex_element in ply
+            .header
+            .elements
+            .keys()
+            .filter(|name| name.starts_with("vertex"))
+        {
+            let suffix = &vertex_element["vertex".len()..];
+            let face_element = format!("face{}", suffix);
+            if !suffix.is_empty() && !ply.payload.contains_key(&face_element) {
+                continue;
+            }
+            let mesh = Self::mesh_from_elements(&ply, vertex_element, &face_element, options)?;
+            let name = suffix.trim_start_matches('_');
+            for part in mesh.split_materials(&ply, &face_element, name) {
+                for chunk in part.mesh.into_chunks(options.wide_indices) {
+                    labels.push((part.name.clone(), part.material_color));
+                    meshes.push(chunk);
+                }
+            }
+        }
+        if meshes.is_empty() {
+            return Err(PlyMeshError::MissingElement("vertex".to_string()));
+        }
+        Self::place_all(&mut meshes, options);
+        Ok(labels
+            .into_iter()
+            .zip(meshes)
+            .map(|((name, material_color), mesh)| PlyPart {
+                name,
+                mesh,
+                material_color,
+            }).collect())
+    }
+
+    fn read<T: Read>(source: &mut T) -> Result<Ply, PlyMeshError> {
+        // ply_rs panics rather than erroring on empty input - a common mistake when loading
+        // user-supplied files - so catch that case up front.
+        let mut bytes = Vec::new();
+        source.read_to_end(&mut bytes)?;
+        if bytes.iter().all(|b| b.is_ascii_whitespace()) {
+            return Err(PlyMeshError::Empty);
+        }
+
+        let parser = ply_rs::parser::Parser::<ply_rs::ply::DefaultElement>::new();
+        Ok(parser.read_ply(&mut bytes.as_slice())?)
+    }
+
+    /// Builds a mesh from the named elements, split as by `parse_chunks_with_options`.
+    fn chunks_from_elements(
+        ply: &Ply,
+        vertex_element: &str,
+        face_element: &str,
+        options: &ParseOptions,
+    ) -> Result<Vec<Self>, PlyMeshError> {
+        let mesh = Self::mesh_from_elements(ply, vertex_element, face_element, options)?;
+        Ok(mesh.into_chunks(options.wide_indices))
+    }
+
+    /// Builds a mesh from the named elements, indexed with `wide_indices` whatever its size.
+    /// Each face becomes a fan of triangles, in the order the faces come in.
+    fn mesh_from_elements(
+        ply: &Ply,
+        vertex_element: &str,
+        face_element: &str,
+        options: &ParseOptions,
+    ) -> Result<Self, PlyMeshError> {
+        let element = |name: &str| {
+            ply.payload
+                .get(name)
+                .ok_or_else(|| PlyMeshError::MissingElement(name.to_string()))
+        };
+        let vertex_data = element(vertex_element)?;
+        // Without faces, as in raw scans, the vertices are loaded as a point cloud.
+        let face_data = ply.payload.get(face_element).map_or(&[][..], |faces| &faces[..]);
+        let declares = |name: &str| {
+            ply.header
+                .elements
+                .get(vertex_element)
+                .map_or(false, |e| e.properties.contains_key(name))
+        };
+
+        // Size the buffers from the element counts up front so that large meshes don't
+        // repeatedly reallocate while collecting.  Faces are assumed to be triangles; anything
+        // else just falls back to growing as normal.
+        let mut vertices = Vec::with_capacity(vertex_data.len() * 3);
+        for vertex in vertex_data {
+            for &key in &["x", "y", "z"] {
+                vertices.push(property(vertex, vertex_element, key, as_f32)?);
+            }
+        }
+        let mut normals = Vec::with_capacity(vertex_data.len() * 3);
+        if declares("nx") {
+            for vertex in vertex_data {
+                for &key in &["nx", "ny", "nz"] {
+                    normals.push(property(vertex, vertex_element, key, as_f32)?);
+                }
+            }
+        }
+        let mut colors = Vec::with_capacity(vertex_data.len() * 3);
+        if declares("red") {
+            for vertex in vertex_data {
+                for &key in &["red", "green", "blue"] {
+                    colors.push(property(vertex, vertex_element, key, as_color)?);
+                }
+            }
+        } else if options.require_colors {
+            return Err(PlyMeshError::MissingColors);
+        }
+        let mut alphas = Vec::new();
+        if declares("alpha") {
+            alphas.reserve(vertex_data.len());
+            for vertex in vertex_data {
+                alphas.push(property(vertex, vertex_element, "alpha", as_color)?);
+            }
+        }
+        let mut texcoords = Vec::new();
+        // Exporters disagree on the names; Blender writes s/t, MeshLab u/v or texture_u/v.
+        let uv_keys = [["u", "v"], ["s", "t"], ["texture_u", "texture_v"]];
+        if let Some(keys) = uv_keys.iter().find(|keys| declares(keys[0])) {
+            texcoords.reserve(vertex_data.len() * 2);
+            for vertex in vertex_data {
+                for &key in keys {
+                    texcoords.push(property(vertex, vertex_element, key, as_f32)?);
+                }
+            }
+        }
+        let mut triangles = Vec::with_capacity(face_data.len() * 3);
+        let faces = face_data
+            .iter()
+            .map(|x| match x.get("vertex_indices") {
+                Some(indices) => {
+                    as_indices(indices).ok_or_else(|| PlyMeshError::UnexpectedProperty {
+                        element: face_element.to_string(),
+                        property: "vertex_indices".to_string(),
+                    })
+                }
+                None => Err(PlyMeshError::MissingProperty {
+                    element: face_element.to_string(),
+                    property: "vertex_indices".to_string(),
+                }),
+            }).collect::<Result<Vec<_>, _>>()?;
+        triangles.extend(faces.iter().flat_map(|x| triangulate_fan(x)));
+        if let Some(&index) = triangles.iter().find(|&&i| i as usize >= vertex_data.len()) {
+            return Err(PlyMeshError::IndexOutOfRange {
+                element: face_element.to_string(),
+                index,
+            });
+        }
+        // Indexed wide until it's known whether it needs splitting, so that vertices on chunk
+        // boundaries still see all their faces when deriving normals.
+        let mut mesh = PlyMesh {
+            vertices,
+            normals,
+            colors,
+            alphas,
+            texcoords,
+            indices: Vec::new(),
+            wide_indices: triangles,
+            origin_offset: Vector3::new(0., 0., 0.),
+            origin_scale: 1.,
+        };
+        if mesh.normals.is_empty() {
+            // Normals derived from the faces always agree with their winding.  Point clouds
+            // have no faces to derive them from, so they're left zero, and lit only by the
+            // ambient light.
+            mesh.recompute_normals();
+        } else {
+            if options.renormalize_normals {
+                mesh.renormalize_normals();
+            }
+            if options.fix_winding {
+                mesh.fix_winding();
+            } else if let Some(threshold) = options.winding_warning_threshold {
+                let disagreement = mesh.winding_disagreement(WINDING_SAMPLE_COUNT);
+                if disagreement > threshold {
+                    console!(log, format!(
+                        "{}: {:.0}% of sampled faces wind against their normals; mesh appears to \
+                         have inconsistent/CW winding; enable fix_winding or switch cull mode",
+                        face_element,
+                        disagreement * 100.
+                    ));
+                }
+            }
+        }
+        // Point clouds have no faces to flatten; converting one would leave it empty.
+        if options.flat_shading && mesh.index_count() > 0 {
+            mesh = mesh.to_flat_shaded();
+        }
+        Ok(mesh)
+    }
+
+    /// Splits a mesh built by `mesh_from_elements` into one part per material its faces pick
+    /// with a `material_index` property, drawn white and tinted with that material's diffuse
+    /// color from the `material` element.  Faces without a valid material, and materials
+    /// without a diffuse color, keep the vertex colors.  Every part keeps the full vertex
+    /// list - only the faces are divided between them.  Parts are named `material<N>` after
+    /// `name`, or just `name` if there's only the one, without a material.
+    fn split_materials(self, ply: &Ply, face_element: &str, name: &str) -> Vec<PlyPart> {
+        let diffuse: Vec<Option<Vector3<f32>>> =
+            ply.payload.get("material").map_or(vec![], |materials| {
+                materials
+                    .iter()
+                    .map(|m| match (
+                        color(m, "diffuse_red"),
+                        color(m, "diffuse_green"),
+                        color(m, "diffuse_blue"),
+                    ) {
+                        (Some(r), Some(g), Some(b)) => Some(vec3(r, g, b)),
+                        _ => None,
+                    }).collect()
+            });
+        let faces = ply.payload.get(face_element).map_or(&[][..], |faces| &faces[..]);
+
+        let mut groups: Vec<(Option<usize>, Vec<u32>)> = Vec::new();
+        // mesh_from_elements fans each face into one triangle per corner past the second.
+        let mut triangles = self.wide_indices.chunks(3);
+        for face in faces {
+            let corners = face
+                .get("vertex_indices")
+                .and_then(as_indices)
+                .map_or(0, |indices| indices.len());
+            let indices = triangles
+                .by_ref()
+                .take(corners.saturating_sub(2))
+                .flat_map(|triangle| triangle.iter().cloned());
+            let material = face
+                .get("material_index")
+                .and_then(as_index)
+                .filter(|&i| i < diffuse.len() && diffuse[i].is_some());
+            match groups.iter_mut().find(|group| group.0 == material) {
+                Some(group) => group.1.extend(indices),
+                None => groups.push((material, indices.collect())),
+            }
+        }
+        if groups.len() <= 1 && groups.iter().all(|group| group.0.is_none()) {
+            return vec![PlyPart {
+                name: name.to_string(),
+                mesh: self,
+                material_color: None,
+            }];
+        }
+
+        groups
+            .into_iter()
+            .map(|(material, indices)| {
+                let (name, colors) = match material {
+                    Some(i) => {
+                        let name = if name.is_empty() {
+                            format!("material{}", i)
+                        } else {
+                            format!("{} material{}", name, i)
+                        };
+                        (name, vec![1.; self.vertices.len()])
+                    }
+                    None => (name.to_string(), self.colors.clone()),
+                };
+                PlyPart {
+                    name,
+                    mesh: PlyMesh {
+                        vertices: self.vertices.clone(),
+                        normals: self.normals.clone(),
+                        colors,
+                        alphas: self.alphas.clone(),
+                        texcoords: self.texcoords.clone(),
+                        indices: Vec::new(),
+                        wide_indices: indices,
+                        origin_offset: self.origin_offset,
+                        origin_scale: self.origin_scale,
+                    },
+                    material_color: material.and_then(|i| diffuse[i]),
+                }
+            }).collect()
+    }
+
+    /// Converts a mesh indexed with `wide_indices` to `u16` indices if they're big enough, or
+    /// else (unless `keep_wide`) splits it into chunks of at most `MAX_CHUNK_VERTICES`
+    /// vertices.  Triangles are kept in order, each chunk taking as many as fit.
+    fn into_chunks(mut self, keep_wide: bool) -> Vec<PlyMesh> {
+        let triangles = mem::replace(&mut self.wide_indices, Vec::new());
+        // Point clouds are drawn without indices, so any number of vertices fit.
+        if self.vertices.len() / 3 <= MAX_CHUNK_VERTICES || triangles.is_empty() {
+            let indices = triangles.iter().map(|&i| i as u16).collect();
+            return vec![PlyMesh { indices, ..self }];
+        } else if keep_wide {
+            return vec![PlyMesh { wide_indices: triangles, ..self }];
+        }
+        let mut chunks = Vec::new();
+        // Each chunk's original vertex indices, and where they've been renumbered to.
+        let mut vertices: Vec<u32> = Vec::new();
+        let mut renumbered: HashMap<u32, u16> = HashMap::new();
+        let mut indices = Vec::new();
+        for triangle in triangles.chunks(3) {
+            let new = triangle.iter().filter(|&&i| !renumbered.contains_key(&i)).count();
+            if vertices.len() + new > MAX_CHUNK_VERTICES {
+                chunks.push(self.gather(&vertices, mem::replace(&mut indices, Vec::new())));
+                vertices.clear();
+                renumbered.clear();
+            }
+            for &i in triangle {
+                let index = *renumbered.entry(i).or_insert_with(|| {
+                    vertices.push(i);
+                    (vertices.len() - 1) as u16
+                });
+                indices.push(index);
+            }
+        }
+        if !indices.is_empty() || chunks.is_empty() {
+            chunks.push(self.gather(&vertices, indices));
+        }
+        chunks
+    }
+
+    /// Copies the given vertices, with all their attributes, into a new mesh.
+    fn gather(&self, vertices: &[u32], indices: Vec<u16>) -> PlyMesh {
+        let pick = |attribute: &[f32], size: usize| -> Vec<f32> {
+            if attribute.is_empty() {
+                return Vec::new();
+            }
+            vertices
+                .iter()
+                .flat_map(|&i| {
+                    let start = i as usize * size;
+                    attribute[start..start + size].iter().cloned()
+                }).collect()
+        };
+        PlyMesh {
+            vertices: pick(&self.vertices, 3),
+            normals: pick(&self.normals, 3),
+            colors: pick(&self.colors, 3),
+            alphas: pick(&self.alphas, 1),
+            texcoords: pick(&self.texcoords, 2),
+            indices,
+            wide_indices: Vec::new(),
+            origin_offset: self.origin_offset,
+            origin_scale: self.origin_scale,
+        }
+    }
+
+    /// A copy of the mesh with every triangle given three vertices of its own, all with the
+    /// triangle's face normal, so it shades flat.  The indices just count up through the new
+    /// vertices, in `wide_indices` if the mesh's were or if there are too many for `u16`s;
+    /// `into_chunks` can then split it.
+    pub fn to_flat_shaded(&self) -> PlyMesh {
+        let corners: Vec<u32> = (0..self.index_count() / 3)
+            .flat_map(|t| self.triangle(t * 3).to_vec())
+            .collect();
+        let mut mesh = self.gather(&corners, Vec::new());
+        let count = corners.len();
+        if self.wide_indices.is_empty() && count <= MAX_CHUNK_VERTICES {
+            mesh.indices = (0..count).map(|i| i as u16).collect();
+        } else {
+            mesh.wide_indices = (0..count as u32).collect();
+        }
+        mesh.normals = mesh
+            .vertices
+            .chunks(9)
+            .flat_map(|t| {
+                let (a, b, c) =
+                    (vec3(t[0], t[1], t[2]), vec3(t[3], t[4], t[5]), vec3(t[6], t[7], t[8]));
+                let n = (b - a).cross(c - a);
+                let n = if n.magnitude2() > 0. { n.normalize() } else { n };
+                vec![n.x, n.y, n.z, n.x, n.y, n.z, n.x, n.y, n.z]
+            }).collect();
+        mesh
+    }
+
+    /// How many indices the mesh has, in whichever of `indices` and `wide_indices` it uses.
+    fn index_count(&self) -> usize {
+        self.indices.len().max(self.wide_indices.len())
+    }
+
+    /// The vertices of the triangle starting at index `start`.
+    fn triangle(&self, start: usize) -> [u32; 3] {
+        if self.wide_indices.is_empty() {
+            let t = &self.indices[start..start + 3];
+            [t[0] as u32, t[1] as u32, t[2] as u32]
+        } else {
+            let t = &self.wide_indices[start..start + 3];
+            [t[0], t[1], t[2]]
+        }
+    }
+
+    /// Whether the triangle starting at index `start` winds counter-clockwise around the
+    /// average of its vertex normals, or `None` if it's degenerate or has no usable normals.
+    fn winds_with_normals(&self, start: usize) -> Option<bool> {
+        let vertex = |i: u32| {
+            let i = i as usize * 3;
+            vec3(self.vertices[i], self.vertices[i + 1], self.vertices[i + 2])
+        };
+        let normal = |i: u32| {
+            let i = i as usize * 3;
+            vec3(self.normals[i], self.normals[i + 1], self.normals[i + 2])
+        };
+        let triangle = self.triangle(start);
+        let (a, b, c) = (vertex(triangle[0]), vertex(triangle[1]), vertex(triangle[2]));
+        let face_normal = (b - a).cross(c - a);
+        let vertex_normal = normal(triangle[0]) + normal(triangle[1]) + normal(triangle[2]);
+        let dot = face_normal.dot(vertex_normal);
+        if dot == 0. {
+            None
+        } else {
+            Some(dot > 0.)
+        }
+    }
+
+    /// The fraction of up to `samples` evenly spaced faces that wind clockwise around their
+    /// vertex normals.
+    pub fn winding_disagreement(&self, samples: usize) -> f32 {
+        let triangles = self.index_count() / 3;
+        if triangles == 0 || samples == 0 {
+            return 0.;
+        }
+        let step = (triangles / samples).max(1);
+        let (checked, disagreeing) = (0..triangles)
+            .step_by(step)
+            .filter_map(|t| self.winds_with_normals(t * 3))
+            .fold((0, 0), |(checked, disagreeing), agrees| {
+                (checked + 1, disagreeing + if agrees { 0 } else { 1 })
+            });
+        if checked == 0 {
+            0.
+        } else {
+            disagreeing as f32 / checked as f32
+        }
+    }
+
+    /// Reverses every face that winds clockwise around its vertex normals, so back-face culling
+    /// keeps the side the normals point out of.
+    pub fn fix_winding(&mut self) {
+        for t in 0..self.index_count() / 3 {
+            if self.winds_with_normals(t * 3) == Some(false) {
+                if self.wide_indices.is_empty() {
+                    self.indices.swap(t * 3 + 1, t * 3 + 2);
+                } else {
+                    self.wide_indices.swap(t * 3 + 1, t * 3 + 2);
+                }
+            }
+        }
+    }
+
+    /// Replaces the normals with smooth per-vertex normals derived from the triangles: each
+    /// vertex gets the normalized sum of its triangles' (area-weighted) face normals.  Used
+    /// for files without normals, but can also be called to discard bad ones.
+    pub fn recompute_normals(&mut self) {
+        self.normals = if self.wide_indices.is_empty() {
+            smooth_normals(&self.vertices, &self.indices)
+        } else {
+            smooth_normals(&self.vertices, &self.wide_indices)
+        };
+    }
+
+    /// Scales every normal to unit length.  Zero-length normals carry no direction to
+    /// preserve, so they're left as they are.
+    pub fn renormalize_normals(&mut self) {
+        for n in self.normals.chunks_mut(3) {
+            let length = (n[0] * n[0] + n[1] * n[1] + n[2] * n[2]).sqrt();
+            if length > 0. {
+                n[0] /= length;
+                n[1] /= length;
+                n[2] /= length;
+            }
+        }
+    }
+}
+
+/// The edges of every triangle in `indices` as pairs of indices, for drawing a mesh as a
+/// wireframe with `gl::LINES`.  Edges shared by two triangles appear twice.
+fn line_indices<I: Copy>(indices: &[I]) -> Vec<I> {
+    indices
+        .chunks(3)
+        .filter(|triangle| triangle.len() == 3)
+        .flat_map(|t| vec![t[0], t[1], t[1], t[2], t[2], t[0]])
+        .collect()
+}
+
+/// Smooth per-vertex normals for the triangles `indices` of `vertices`; see
+/// `PlyMesh::recompute_normals`.
+fn smooth_normals<I: Copy + Into<u32>>(vertices: &[f32], indices: &[I]) -> Vec<f32> {
+    let vertex = |i: I| {
+        let i = i.into() as usize * 3;
+        vec3(vertices[i], vertices[i + 1], vertices[i + 2])
+    };
+    let mut normals = vec![vec3(0f32, 0., 0.); vertices.len() / 3];
+    for triangle in indices.chunks(3).filter(|triangle| triangle.len() == 3) {
+        let (a, b, c) = (vertex(triangle[0]), vertex(triangle[1]), vertex(triangle[2]));
+        let normal = (b - a).cross(c - a);
+        for &i in triangle {
+            normals[i.into() as usize] += normal;
+        }
+    }
+    normals
+        .into_iter()
+        .flat_map(|n| {
+            let n = if n.magnitude2() > 0. { n.normalize() } else { n };
+            vec![n.x, n.y, n.z]
+        }).collect()
+}
+
+/// Converts any scalar PLY property to `f32`.  Signed types are sign-extended, so an exporter
+/// writing coordinates as `char`/`short`/`int` gets the values it meant.
+fn as_f32(property: &ply_rs::ply::Property) -> Option<f32> {
+    use ply_rs::ply::Property;
+    match *property {
+        Property::Char(x) => Some(x as f32),
+        Property::UChar(x) => Some(x as f32),
+        Property::Short(x) => Some(x as f32),
+        Property::UShort(x) => Some(x as f32),
+        Property::Int(x) => Some(x as f32),
+        Property::UInt(x) => Some(x as f32),
+        Property::Float(x) => Some(x),
+        Property::Double(x) => Some(x as f32),
+        _ => None,
+    }
+}
+
+/// Converts a PLY color channel to [0, 1].  Integer types are scaled by their maximum value;
+/// signed ones can't represent more than half the range, so negative values clamp to 0 and the
+/// positive half is stretched to fill [0, 1].  Floating-point channels are taken as-is.
+fn as_color(property: &ply_rs::ply::Property) -> Option<f32> {
+    use ply_rs::ply::Property;
+    let value = match *property {
+        Property::Char(x) => x as f32 / i8::max_value() as f32,
+        Property::UChar(x) => x as f32 / u8::max_value() as f32,
+        Property::Short(x) => x as f32 / i16::max_value() as f32,
+        Property::UShort(x) => x as f32 / u16::max_value() as f32,
+        Property::Int(x) => x as f32 / i32::max_value() as f32,
+        Property::UInt(x) => x as f32 / u32::max_value() as f32,
+        Property::Float(x) => x,
+        Property::Double(x) => x as f32,
+        _ => return None,
+    };
+    Some(value.max(0.).min(1.))
+}
+
+/// Reads an integer property as an index, e.g. a face's `material_index`.  Negative values
+/// (which exporters use for "none") and non-integers give `None`.
+fn as_index(property: &ply_rs::ply::Property) -> Option<usize> {
+    use ply_rs::ply::Property;
+    let index = match *property {
+        Property::Char(x) => x as i64,
+        Property::UChar(x) => x as i64,
+        Property::Short(x) => x as i64,
+        Property::UShort(x) => x as i64,
+        Property::Int(x) => x as i64,
+        Property::UInt(x) => x as i64,
+        _ => return None,
+    };
+    if index < 0 {
+        None
+    } else {
+        Some(index as usize)
+    }
+}
+
+/// Converts a face's vertex index list to `u32`s, whatever integer type the file stores them
+/// as: Blender writes `uint` indices, while MeshLab and many binary exporters write `int`.
+/// Negative indices are invalid, so they give `None`.
+fn as_indices(property: &ply_rs::ply::Property) -> Option<Vec<u32>> {
+    use ply_rs::ply::Property;
+    fn unsigned<T: Copy + Into<i64>>(list: &[T]) -> Option<Vec<u32>> {
+        list.iter()
+            .map(|&i| {
+                let i: i64 = i.into();
+                if i >= 0 && i <= u32::max_value() as i64 {
+                    Some(i as u32)
+                } else {
+                    None
+                }
+            }).collect()
+    }
+    match *property {
+        Property::ListChar(ref x) => unsigned(x),
+        Property::ListUChar(ref x) => unsigned(x),
+        Property::ListShort(ref x) => unsigned(x),
+        Property::ListUShort(ref x) => unsigned(x),
+        Property::ListInt(ref x) => unsigned(x),
+        Property::ListUInt(ref x) => Some(x.clone()),
+        _ => None,
+    }
+}
+
+/// Splits a polygon face `[a, b, c, d, ...]` into the triangle fan `(a, b, c), (a, c, d), ...`.
+/// Faces are assumed convex, as PLY exporters generally write them; anything with fewer than
+/// three vertices yields nothing.
+fn triangulate_fan<'a>(face: &'a [u32]) -> impl Iterator<Item = u32> + 'a {
+    (2..face.len()).flat_map(move |i| vec![face[0], face[i - 1], face[i]])
+}
+
+/// Reads property `key` of one of `element_name`'s elements with `convert`, distinguishing
+/// a missing property from one of the wrong type.
+fn property(
+    element: &ply_rs::ply::DefaultElement,
+    element_name: &str,
+    key: &str,
+    convert: fn(&ply_rs::ply::Property) -> Option<f32>,
+) -> Result<f32, PlyMeshError> {
+    match element.get(key) {
+        Some(value) => convert(value).ok_or_else(|| PlyMeshError::UnexpectedProperty {
+            element: element_name.to_string(),
+            property: key.to_string(),
+        }),
+        None => Err(PlyMeshError::MissingProperty {
+            element: element_name.to_string(),
+            property: key.to_string(),
+        }),
+    }
+}
+
+fn color(element: &ply_rs::ply::DefaultElement, key: &str) -> Option<f32> {
+    element.get(key).and_then(as_color)
+}
+
+/// Writes `mesh` out as an ASCII PLY file that `PlyMesh::parse` can read back.  Normals and
+/// colors are only written if the mesh has one per vertex; colors are quantized to bytes.
+fn write_ascii_ply<W: Write>(mesh: &dyn Mesh, w: &mut W) -> io::Result<()> {
+    let vertices = mesh.vertices();
+    let vertex_count = vertices.len() / 3;
+    let normals = Some(mesh.normals()).filter(|n| n.len() == vertices.len());
+    let colors = Some(mesh.colors()).filter(|c| c.len() == vertices.len());
+
+    writeln!(w, "ply")?;
+    writeln!(w, "format ascii 1.0")?;
+    writeln!(w, "element vertex {}", vertex_count)?;
+    writeln!(w, "property float x")?;
+    writeln!(w, "property float y")?;
+    writeln!(w, "property float z")?;
+    if normals.is_some() {
+        writeln!(w, "property float nx")?;
+        writeln!(w, "property float ny")?;
+        writeln!(w, "property float nz")?;
+    }
+    if colors.is_some() {
+        writeln!(w, "property uchar red")?;
+        writeln!(w, "property uchar green")?;
+        writeln!(w, "property uchar blue")?;
+    }
+    writeln!(w, "element face {}", mesh.triangles().count())?;
+    writeln!(w, "property list uchar uint vertex_indices")?;
+    writeln!(w, "end_header")?;
+
+    for i in 0..vertex_count {
+        let v = &vertices[i * 3..i * 3 + 3];
+        write!(w, "{} {} {}", v[0], v[1], v[2])?;
+        if let Some(normals) = normals {
+            let n = &normals[i * 3..i * 3 + 3];
+            write!(w, " {} {} {}", n[0], n[1], n[2])?;
+        }
+        if let Some(colors) = colors {
+            let byte = |c: f32| (c.max(0.).min(1.) * 255.).round() as u8;
+            let c = &colors[i * 3..i * 3 + 3];
+            write!(w, " {} {} {}", byte(c[0]), byte(c[1]), byte(c[2]))?;
+        }
+        writeln!(w)?;
+    }
+    for face in mesh.triangles() {
+        writeln!(w, "3 {} {} {}", face[0], face[1], face[2])?;
+    }
+    Ok(())
+}
+
+impl Mesh for PlyMesh {
+    fn vertices(&self) -> &[f32] {
+        self.vertices.as_slice()
+    }
+    fn normals(&self) -> &[f32] {
+        self.normals.as_slice()
+    }
+    fn colors(&self) -> &[f32] {
+        self.colors.as_slice()
+    }
+    fn alphas(&self) -> &[f32] {
+        self.alphas.as_slice()
+    }
+    fn texcoords(&self) -> &[f32] {
+        self.texcoords.as_slice()
+    }
+    fn indices(&self) -> &[u16] {
+        self.indices.as_slice()
+    }
+    fn wide_indices(&self) -> &[u32] {
+        self.wide_indices.as_slice()
+    }
+}
+
+/// A mesh read from a Wavefront OBJ file.  OBJ indexes positions, texture coordinates and
+/// normals separately, so every distinct combination used by a face corner becomes a vertex of
+/// its own.  Files without `vn` normals get smooth ones derived from the faces, and ones
+/// without vertex colors (a common extension: `v x y z r g b`) are drawn gray.
+#[derive(Default)]
+struct ObjMesh {
+    vertices: Vec<f32>,
+    normals: Vec<f32>,
+    colors: Vec<f32>,
+    texcoords: Vec<f32>,
+    indices: Vec<u16>,
+}
+
+#[derive(Debug)]
+enum ObjError {
+    /// The source could not be read.
+    Io(io::Error),
+    /// The source has no faces.
+    Empty,
+    /// The line with this (1-based) number couldn't be parsed.
+    Syntax(usize),
+    /// A face on this line refers to a vertex, normal or texture coordinate that isn't
+    /// defined (yet).
+    IndexOutOfRange(usize),
+    /// The faces use more distinct vertices than 16-bit indices can address.
+    TooManyVertices,
+    /// The request for the data failed with this HTTP status, or 0 if there was no response
+    /// at all.
+    Http(u16),
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ObjError::Io(ref err) => write!(f, "couldn't read OBJ data: {}", err),
+            ObjError::Empty => write!(f, "OBJ data has no faces"),
+            ObjError::Syntax(line) => write!(f, "OBJ line {} is malformed", line),
+            ObjError::IndexOutOfRange(line) => {
+                write!(f, "OBJ face on line {} refers to a missing element", line)
+            }
+            ObjError::TooManyVertices => {
+                write!(f, "OBJ mesh has more than {} vertices", MAX_CHUNK_VERTICES)
+            }
+            ObjError::Http(0) => write!(f, "couldn't fetch OBJ data: no response"),
+            ObjError::Http(status) => write!(f, "couldn't fetch OBJ data: HTTP {}", status),
+        }
+    }
+}
+
+impl Error for ObjError {}
+
+impl From<io::Error> for ObjError {
+    fn from(err: io::Error) -> Self {
+        ObjError::Io(err)
+    }
+}
+
+impl ObjMesh {
+    pub fn parse<T: Read>(source: &mut T) -> Result<Self, ObjError> {
+        let mut text = String::new();
+        source.read_to_string(&mut text)?;
+
+        let mut positions: Vec<[f32; 3]> = Vec::new();
+        let mut vertex_colors: Vec<Option<[f32; 3]>> = Vec::new();
+        let mut normals: Vec<[f32; 3]> = Vec::new();
+        let mut uvs: Vec<[f32; 2]> = Vec::new();
+        // The vertex made for each (position, texture coordinate, normal) combination so far.
+        let mut corners: HashMap<(usize, Option<usize>, Option<usize>), u32> = HashMap::new();
+        let (mut all_normals, mut all_uvs, mut any_colors) = (true, true, false);
+        let mut mesh = ObjMesh::default();
+        let mut triangles = Vec::new();
+
+        for (number, line) in text.lines().enumerate() {
+            let number = number + 1;
+            let mut words = line.split('#').next().unwrap_or("").split_whitespace();
+            let keyword = words.next();
+            let values = || -> Result<Vec<f32>, ObjError> {
+                words
+                    .clone()
+                    .map(|word| word.parse::<f32>().map_err(|_| ObjError::Syntax(number)))
+                    .collect()
+            };
+            match keyword {
+                Some("v") => {
+                    let v = values()?;
+                    match v.len() {
+                        // An optional weight, which we ignore.
+                        3 | 4 => vertex_colors.push(None),
+                        6 => {
+                            vertex_colors.push(Some([v[3], v[4], v[5]]));
+                            any_colors = true;
+                        }
+                        _ => return Err(ObjError::Syntax(number)),
+                    }
+                    positions.push([v[0], v[1], v[2]]);
+                }
+                Some("vn") => {
+                    let n = values()?;
+                    if n.len() != 3 {
+                        return Err(ObjError::Syntax(number));
+                    }
+                    normals.push([n[0], n[1], n[2]]);
+                }
+                Some("vt") => {
+                    let t = values()?;
+                    if t.len() < 2 {
+                        return Err(ObjError::Syntax(number));
+                    }
+                    uvs.push([t[0], t[1]]);
+                }
+                Some("f") => {
+                    let mut face = Vec::new();
+                    for corner in words {
+                        let mut parts = corner.split('/');
+                        let position = obj_index(parts.next(), positions.len(), number)?
+                            .ok_or(ObjError::Syntax(number))?;
+                        let uv = obj_index(parts.next(), uvs.len(), number)?;
+                        let normal = obj_index(parts.next(), normals.len(), number)?;
+                        all_uvs &= uv.is_some();
+                        all_normals &= normal.is_some();
+                        let next = corners.len() as u32;
+                        let vertex = *corners.entry((position, uv, normal)).or_insert_with(|| {
+                            mesh.vertices.extend_from_slice(&positions[position]);
+                            let color = vertex_colors[position].unwrap_or([0.8; 3]);
+                            mesh.colors.extend_from_slice(&color);
+                            mesh.normals.extend_from_slice(&normal.map_or([0.; 3], |n| normals[n]));
+                            mesh.texcoords.extend_from_slice(&uv.map_or([0.; 2], |t| uvs[t]));
+                            next
+                        });
+                        face.push(vertex);
+                    }
+                    if face.len() < 3 {
+                        return Err(ObjError::Syntax(number));
+                    }
+                    triangles.extend(triangulate_fan(&face));
+                }
+                // Groups, materials, smoothing groups, lines etc. don't affect the geometry.
+                _ => {}
+            }
+        }
+
+        if triangles.is_empty() {
+            return Err(ObjError::Empty);
+        }
+        if corners.len() > MAX_CHUNK_VERTICES {
+            return Err(ObjError::TooManyVertices);
+        }
+        if !all_normals {
+            mesh.normals = smooth_normals(&mesh.vertices, &triangles);
+        }
+        if !all_uvs {
+            mesh.texcoords.clear();
+        }
+        if !any_colors {
+            mesh.colors.clear();
+        }
+        mesh.indices = triangles.into_iter().map(|i| i as u16).collect();
+        Ok(mesh)
+    }
+}
+
+/// Resolves one part of an OBJ face corner (e.g. the `2` of `1/2/3`) to a 0-based index into
+/// a list of `len` elements.  OBJ counts from 1, or backwards from the end if negative; an
+/// absent or empty part (as in `1//3`) gives `None`.
+fn obj_index(part: Option<&str>, len: usize, line: usize) -> Result<Option<usize>, ObjError> {
+    let part = match part {
+        Some(part) if !part.is_empty() => part,
+        _ => return Ok(None),
+    };
+    let index: isize = part.parse().map_err(|_| ObjError::Syntax(line))?;
+    let index = if index < 0 { len as isize + index } else { index - 1 };
+    if index < 0 || index as usize >= len {
+        return Err(ObjError::IndexOutOfRange(line));
+    }
+    Ok(Some(index as usize))
+}
+
+impl Mesh for ObjMesh {
+    fn vertices(&self) -> &[f32] {
+        self.vertices.as_slice()
+    }
+    fn normals(&self) -> &[f32] {
+        self.normals.as_slice()
+    }
+    fn colors(&self) -> &[f32] {
+        self.colors.as_slice()
+    }
+    fn texcoords(&self) -> &[f32] {
+        self.texcoords.as_slice()
+    }
+    fn indices(&self) -> &[u16] {
+        self.indices.as_slice()
+    }
+}
+
+/// Slope-scaled factor and constant units by which objects with a wire overlay are pushed back;
+/// see `State::draw_object`.
+const WIRE_OVERLAY_OFFSET: (f32, f32) = (1., 1.);
+
+/// How much of a metal's color is left in its diffuse term; the rest goes into its highlights.
+const METAL_DIFFUSE: f32 = 0.25;
+
+/// Presets for how a mesh reflects light; see `BoundMesh::set_finish`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Finish {
+    /// Stone, plastic and the like: white highlights over the mesh's own colors.
+    Dielectric,
+    /// Highlights in this color (e.g. `vec3(1., 0.78, 0.34)` for gold), over a darkened
+    /// diffuse tinted the same.
+    Metal(Vector3<f32>),
+}
+
+/// How a mesh's colors are stored in its GPU buffer.  `Float` keeps the full precision of the
+/// source data; `UnsignedByte` quantizes each channel to 8 bits (which is all most PLY colors
+/// have anyway) and has the GPU normalize them back to [0, 1], making the color buffer a
+/// quarter of the size - worthwhile for very large colored point clouds.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ColorFormat {
+    Float,
+    UnsignedByte,
+}
+
+impl ColorFormat {
+    /// The `type` and `normalized` arguments to pass to `vertex_attrib_pointer`.
+    pub fn attrib_type(self) -> (u32, bool) {
+        match self {
+            ColorFormat::Float => (gl::FLOAT, false),
+            ColorFormat::UnsignedByte => (gl::UNSIGNED_BYTE, true),
+        }
+    }
+}
+
+/// What a `BoundMesh` is drawn as; see `Mesh::primitive`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum PrimitiveType {
+    /// Indexed triangles, drawn with `draw_elements`.
+    Triangles,
+    /// Indexed triangles in a single strip (see `Mesh::strip_indices`), drawn with
+    /// `draw_elements`.
+    TriangleStrip,
+    /// Every vertex as a point, drawn with `draw_arrays`.
+    Points,
+}
+
+/// How a `BoundMesh`'s vertex attributes are laid out in its buffers.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum VertexLayout {
+    /// One tightly packed buffer per attribute.
+    Separate,
+    /// A single buffer of `[px, py, pz, nx, ny, nz, r, g, b]` per vertex, shared by
+    /// `vertex_buffer`, `normal_buffer` and `color_buffer`.  Colors are always floats.
+    Interleaved,
+}
+
+/// The vertex attribute buffers a mesh was uploaded to, for `BoundMesh::new`.  Under
+/// `VertexLayout::Interleaved`, `vertices`, `normals` and `colors` are the same buffer.
+struct VertexBuffers {
+    pub vertices: WebGLBuffer,
+    pub normals: WebGLBuffer,
+    pub colors: WebGLBuffer,
+    pub color_format: ColorFormat,
+    pub layout: VertexLayout,
+    pub alphas: Option<WebGLBuffer>,
+    pub texcoords: Option<WebGLBuffer>,
+}
+
+/// The index buffers a mesh was uploaded to, for `BoundMesh::new`; see `Mesh::bind_indices`.
+struct IndexBuffers {
+    pub triangles: WebGLBuffer,
+    pub num_indices: u32,
+    /// The triangles' edges; see `line_indices`.
+    pub lines: WebGLBuffer,
+    pub num_line_indices: u32,
+    /// `gl::UNSIGNED_SHORT` or `gl::UNSIGNED_INT`, for both buffers.
+    pub index_type: u32,
+}
+
+struct BoundMesh {
+    /// The context the buffers belong to; see `belongs_to`.
+    context: WebGLRenderingContext,
+    pub primitive: PrimitiveType,
+    pub num_vertices: u32,
+    pub num_indices: u32,
+    pub vertex_buffer: WebGLBuffer,
+    pub normal_buffer: WebGLBuffer,
+    pub color_buffer: WebGLBuffer,
+    pub color_format: ColorFormat,
+    pub layout: VertexLayout,
+    /// Per-vertex opacity, for meshes that have it; see `Mesh::alphas`.
+    pub alpha_buffer: Option<WebGLBuffer>,
+    /// Texture coordinates, for meshes that have them; see `Mesh::texcoords`.
+    pub uv_buffer: Option<WebGLBuffer>,
+    /// Sampled with `uv_buffer` to color the mesh.  Not owned: it may be shared between
+    /// meshes, so it isn't deleted with them.
+    pub texture: Option<WebGLTexture>,
+    /// Multiplies the vertex colors, so separately bound copies of a mesh can be told apart.
+    /// White by default.
+    pub material_color: Vector3<f32>,
+    /// Tints the specular highlights, independently of the diffuse color.  White by default,
+    /// as for most materials; see `set_finish`.
+    pub specular_color: Vector3<f32>,
+    pub index_buffer: WebGLBuffer,
+    /// `gl::UNSIGNED_SHORT`, or `gl::UNSIGNED_INT` if bound from `Mesh::wide_indices`.  Shared
+    /// by both index buffers.
+    pub index_type: u32,
+    /// Indices of the triangles' edges; see `line_indices`.
+    pub num_line_indices: u32,
+    pub line_index_buffer: WebGLBuffer,
+}
+
+impl BoundMesh {
+    pub fn new(
+        context: WebGLRenderingContext,
+        primitive: PrimitiveType,
+        num_vertices: u32,
+        vertices: VertexBuffers,
+        indices: IndexBuffers,
+    ) -> Self {
+        BoundMesh {
+            context,
+            primitive,
+            num_vertices,
+            num_indices: indices.num_indices,
+            vertex_buffer: vertices.vertices,
+            normal_buffer: vertices.normals,
+            color_buffer: vertices.colors,
+            color_format: vertices.color_format,
+            layout: vertices.layout,
+            alpha_buffer: vertices.alphas,
+            uv_buffer: vertices.texcoords,
+            texture: None,
+            material_color: vec3(1., 1., 1.),
+            specular_color: vec3(1., 1., 1.),
+            index_buffer: indices.triangles,
+            index_type: indices.index_type,
+            num_line_indices: indices.num_line_indices,
+            line_index_buffer: indices.lines,
+        }
+    }
+
+    /// Sets `material_color` and `specular_color` to give the mesh `finish`.
+    pub fn set_finish(&mut self, finish: Finish) {
+        match finish {
+            Finish::Dielectric => {
+                self.material_color = vec3(1., 1., 1.);
+                self.specular_color = vec3(1., 1., 1.);
+            }
+            Finish::Metal(color) => {
+                self.specular_color = color;
+                self.material_color = color * METAL_DIFFUSE;
+            }
+        }
+    }
+
+    /// Draws the triangles in `mode`, or every vertex as a point for a point cloud, with
+    /// whatever attributes are currently set up.  Strips are always drawn as strips.
+    pub fn draw(&self, mode: u32) {
+        match self.primitive {
+            PrimitiveType::Points => {
+                self.context
+                    .draw_arrays(gl::POINTS, 0, self.num_vertices as i32)
+            }
+            PrimitiveType::Triangles => {
+                self.context
+                    .bind_buffer(gl::ELEMENT_ARRAY_BUFFER, Some(&self.index_buffer));
+                self.context
+                    .draw_elements(mode, self.num_indices as i32, self.index_type, 0);
+            }
+            PrimitiveType::TriangleStrip => {
+                self.context
+                    .bind_buffer(gl::ELEMENT_ARRAY_BUFFER, Some(&self.index_buffer));
+                self.context.draw_elements(
+                    gl::TRIANGLE_STRIP,
+                    self.num_indices as i32,
+                    self.index_type,
+                    0,
+                );
+            }
+        }
+    }
+
+    /// Draws the edges of the triangles as lines, from `line_index_buffer`.
+    pub fn draw_edges(&self) {
+        self.context
+            .bind_buffer(gl::ELEMENT_ARRAY_BUFFER, Some(&self.line_index_buffer));
+        self.context
+            .draw_elements(gl::LINES, self.num_line_indices as i32, self.index_type, 0);
+    }
+
+    /// Points attribute `index` at the mesh's positions.  Leaves the vertex buffer bound.
+    pub fn point_positions(&self, index: u32) {
+        self.context.bind_buffer(gl::ARRAY_BUFFER, Some(&self.vertex_buffer));
+        self.context.vertex_attrib_pointer(index, 3, gl::FLOAT, false, self.stride(), 0);
+    }
+
+    /// Points attribute `index` at the mesh's normals.  Leaves the normal buffer bound.
+    pub fn point_normals(&self, index: u32) {
+        let offset = match self.layout {
+            VertexLayout::Separate => 0,
+            VertexLayout::Interleaved => 3 * 4,
+        };
+        self.context.bind_buffer(gl::ARRAY_BUFFER, Some(&self.normal_buffer));
+        self.context.vertex_attrib_pointer(index, 3, gl::FLOAT, false, self.stride(), offset);
+    }
+
+    /// Points attribute `index` at the mesh's colors.  Leaves the color buffer bound.
+    pub fn point_colors(&self, index: u32) {
+        let offset = match self.layout {
+            VertexLayout::Separate => 0,
+            VertexLayout::Interleaved => 6 * 4,
+        };
+        let (color_type, color_normalized) = self.color_format.attrib_type();
+        self.context.bind_buffer(gl::ARRAY_BUFFER, Some(&self.color_buffer));
+        self.context.vertex_attrib_pointer(
+            index,
+            3,
+            color_type,
+            color_normalized,
+            self.stride(),
+            offset,
+        );
+    }
+
+    /// Whether any of the mesh's vertices may be translucent, so it needs blending.
+    pub fn has_alpha(&self) -> bool {
+        self.alpha_buffer.is_some()
+    }
+
+    /// Points attribute `index` at the mesh's alphas, or sets it to a constant 1 (enabling or
+    /// disabling its array to suit) if the mesh is opaque.
+    pub fn point_alphas(&self, index: u32) {
+        match self.alpha_buffer {
+            Some(ref alpha_buffer) => {
+                self.context.enable_vertex_attrib_array(index);
+                self.context.bind_buffer(gl::ARRAY_BUFFER, Some(alpha_buffer));
+                self.context.vertex_attrib_pointer(index, 1, gl::FLOAT, false, 0, 0);
+            }
+            None => {
+                self.context.disable_vertex_attrib_array(index);
+                self.context.vertex_attrib1f(index, 1.);
+            }
+        }
+    }
+
+    /// Whether the mesh has both texture coordinates and a texture to sample with them.
+    pub fn is_textured(&self) -> bool {
+        self.uv_buffer.is_some() && self.texture.is_some()
+    }
+
+    /// Points attribute `index` at the mesh's texture coordinates, or sets it to a constant
+    /// (0, 0) if it has none, like `point_alphas`.
+    pub fn point_texcoords(&self, index: u32) {
+        match self.uv_buffer {
+            Some(ref uv_buffer) => {
+                self.context.enable_vertex_attrib_array(index);
+                self.context.bind_buffer(gl::ARRAY_BUFFER, Some(uv_buffer));
+                self.context.vertex_attrib_pointer(index, 2, gl::FLOAT, false, 0, 0);
+            }
+            None => {
+                self.context.disable_vertex_attrib_array(index);
+                self.context.vertex_attrib2f(index, 0., 0.);
+            }
+        }
+    }
+
+    /// Bytes between consecutive vertices in each buffer, with 0 meaning tightly packed.
+    fn stride(&self) -> i32 {
+        match self.layout {
+            VertexLayout::Separate => 0,
+            VertexLayout::Interleaved => 9 * 4,
+        }
+    }
+
+    /// Whether this mesh's buffers were created in `context`.  Buffers only exist in the
+    /// context that created them, and drawing them in another fails without any error, so
+    /// binding the same `Mesh` to several contexts gives a `BoundMesh` per context.
+    pub fn belongs_to(&self, context: &WebGLRenderingContext) -> bool {
+        AsRef::<Reference>::as_ref(&self.context) == AsRef::<Reference>::as_ref(context)
+    }
+}
+
+impl Drop for BoundMesh {
+    /// Frees the buffers, which would otherwise live as long as the context does.
+    fn drop(&mut self) {
+        self.context.delete_buffer(Some(&self.vertex_buffer));
+        if self.layout == VertexLayout::Separate {
+            self.context.delete_buffer(Some(&self.normal_buffer));
+            self.context.delete_buffer(Some(&self.color_buffer));
+        }
+        for buffer in self.alpha_buffer.iter().chain(self.uv_buffer.iter()) {
+            self.context.delete_buffer(Some(buffer));
+        }
+        self.context.delete_buffer(Some(&self.index_buffer));
+        self.context.delete_buffer(Some(&self.line_index_buffer));
+    }
+}
+
+#[derive(Debug)]
+enum ShaderError {
+    /// The context couldn't create a shader or program object, e.g. because it was lost.
+    Create,
+    /// A shader failed to compile; holds the stage ("vertex" or "fragment") and the compiler
+    /// log.
+    Compile(&'static str, String),
+    /// The program failed to link; holds the linker log.
+    Link(String),
+}
+
+impl fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ShaderError::Create => write!(f, "couldn't create shader objects"),
+            ShaderError::Compile(stage, ref log) => {
+                write!(f, "{} shader failed to compile: {}", stage, log)
+            }
+            ShaderError::Link(ref log) => write!(f, "shader program failed to link: {}", log),
+        }
+    }
+}
+
+impl Error for ShaderError {}
+
+/// A mesh placed in the scene, with children that move with it.
+struct SceneNode {
+    /// Identifies the node for picking reports and `find_mut`.
+    pub name: Cow<'static, str>,
+    pub mesh: BoundMesh,
+    /// Placement relative to the parent node.
+    pub transform: Matrix4<f32>,
+    /// Draw the edges of the mesh's triangles over it, in `Settings::wire_overlay_color`.
+    pub wire_overlay: bool,
+    pub children: Vec<SceneNode>,
+}
+
+impl SceneNode {
+    pub fn new<N>(name: N, mesh: BoundMesh, transform: Matrix4<f32>) -> Self
+    where
+        N: Into<Cow<'static, str>>,
+    {
+        SceneNode {
+            name: name.into(),
+            mesh,
+            transform,
+            wire_overlay: false,
+            children: Vec::new(),
+        }
+    }
+
+    pub fn with_child(mut self, child: SceneNode) -> Self {
+        self.children.push(child);
+        self
+    }
+
+    /// Calls `visit` with every node in the tree and its world matrix, parents before their
+    /// children.
+    pub fn walk<'a, F>(&'a self, parent: Matrix4<f32>, visit: &mut F)
+    where
+        F: FnMut(&'a SceneNode, Matrix4<f32>),
+    {
+        let world = parent * self.transform;
+        visit(self, world);
+        for child in &self.children {
+            child.walk(world, visit);
+        }
+    }
+
+    /// The world matrix of the node called `name`, if there is one.
+    pub fn world_matrix(&self, name: &str) -> Option<Matrix4<f32>> {
+        let mut found = None;
+        self.walk(Matrix4::identity(), &mut |node, world| {
+            if node.name == name {
+                found = Some(world);
+            }
+        });
+        found
+    }
+
+    /// The node `index` places into the tree in `walk` order.
+    pub fn nth_mut(&mut self, mut index: usize) -> Option<&mut SceneNode> {
+        fn nth<'a>(node: &'a mut SceneNode, index: &mut usize) -> Option<&'a mut SceneNode> {
+            if *index == 0 {
+                return Some(node);
+            }
+            *index -= 1;
+            for child in &mut node.children {
+                if let Some(found) = nth(child, index) {
+                    return Some(found);
+                }
+            }
+            None
+        }
+        nth(self, &mut index)
+    }
+
+    pub fn find_mut(&mut self, name: &str) -> Option<&mut SceneNode> {
+        if self.name == name {
+            return Some(self);
+        }
+        self.children
+            .iter_mut()
+            .filter_map(|child| child.find_mut(name))
+            .next()
+    }
+}
+
+struct Shader {
+    pub program: WebGLProgram,
+}
+
+impl Shader {
+    /// Compiles and links a program, panicking with the compiler or linker log on failure.
+    /// Use `compile` for shaders that may legitimately fail, e.g. user-supplied ones.
+    pub fn new(context: &WebGLRenderingContext, vertex_code: &str, fragment_code: &str) -> Self {
+        Self::compile(context, vertex_code, fragment_code).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub fn compile(
+        context: &WebGLRenderingContext,
+        vertex_code: &str,
+        fragment_code: &str,
+    ) -> Result<Self, ShaderError> {
+        let vertex_shader =
+            Self::compile_stage(context, gl::VERTEX_SHADER, "vertex", vertex_code)?;
+        let fragment_shader =
+            Self::compile_stage(context, gl::FRAGMENT_SHADER, "fragment", fragment_code)?;
+
+        let program = context.create_program().ok_or(ShaderError::Create)?;
+        context.attach_shader(&program, &vertex_shader);
+        context.attach_shader(&program, &fragment_shader);
+        context.link_program(&program);
+        // The program keeps the shaders alive for as long as it needs them.
+        context.delete_shader(Some(&vertex_shader));
+        context.delete_shader(Some(&fragment_shader));
+        let linked = context
+            .get_program_parameter(&program, gl::LINK_STATUS)
+            .try_into()
+            .unwrap_or(false);
+        if !linked {
+            let log = context.get_program_info_log(&program).unwrap_or_default();
+            context.delete_program(Some(&program));
+            return Err(ShaderError::Link(log));
+        }
+
+        Ok(Shader { program })
+    }
+
+    fn compile_stage(
+        context: &WebGLRenderingContext,
+        kind: u32,
+        stage: &'static str,
+        code: &str,
+    ) -> Result<WebGLShader, ShaderError> {
+        let shader = context.create_shader(kind).ok_or(ShaderError::Create)?;
+        context.shader_source(&shader, code);
+        context.compile_shader(&shader);
+        let compiled = context
+            .get_shader_parameter(&shader, gl::COMPILE_STATUS)
+            .try_into()
+            .unwrap_or(false);
+        if !compiled {
+            let log = context.get_shader_info_log(&shader).unwrap_or_default();
+            context.delete_shader(Some(&shader));
+            return Err(ShaderError::Compile(stage, log));
+        }
+        Ok(shader)
+    }
+}
+
+/// A quad covering the whole viewport, for passes that draw every pixel (e.g. displaying
+/// offscreen textures).  Vertex shaders drawing it receive clip-space `vec2` positions.
+struct FullScreenQuad {
+    buffer: WebGLBuffer,
+}
+
+impl FullScreenQuad {
+    pub fn new(context: &WebGLRenderingContext) -> Self {
+        let vertices = TypedArray::<f32>::from(&[-1., -1., 1., -1., -1., 1., 1., 1.][..]).buffer();
+        let buffer = context.create_buffer().unwrap();
+        context.bind_buffer(gl::ARRAY_BUFFER, Some(&buffer));
+        context.buffer_data_1(gl::ARRAY_BUFFER, Some(&vertices), gl::STATIC_DRAW);
+        FullScreenQuad { buffer }
+    }
+
+    /// Draws the quad with its corners fed to attribute `position`, if the shader has one.
+    pub fn draw(&self, context: &WebGLRenderingContext, position: Option<u32>) {
+        context.bind_buffer(gl::ARRAY_BUFFER, Some(&self.buffer));
+        if let Some(position) = position {
+            context.enable_vertex_attrib_array(position);
+            context.vertex_attrib_pointer(position, 2, gl::FLOAT, false, 0, 0);
+        }
+        context.draw_arrays(gl::TRIANGLE_STRIP, 0, 4);
+        if let Some(position) = position {
+            context.disable_vertex_attrib_array(position);
+        }
+    }
+}
+
+/// An offscreen RGBA texture, optionally with a depth buffer, for passes that render the scene
+/// somewhere other than the canvas.
+struct RenderTarget {
+    pub framebuffer: WebGLFramebuffer,
+    pub texture: WebGLTexture,
+    depth_buffer: Option<WebGLRenderbuffer>,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl RenderTarget {
+    pub fn new(context: &WebGLRenderingContext, width: i32, height: i32, depth: bool) -> Self {
+        let texture = context.create_texture().unwrap();
+        context.bind_texture(gl::TEXTURE_2D, Some(&texture));
+        context.tex_parameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
+        context.tex_parameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+        context.tex_parameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+        context.tex_parameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+
+        let framebuffer = context.create_framebuffer().unwrap();
+        context.bind_framebuffer(gl::FRAMEBUFFER, Some(&framebuffer));
+        context.framebuffer_texture2_d(
+            gl::FRAMEBUFFER,
+            gl::COLOR_ATTACHMENT0,
+            gl::TEXTURE_2D,
+            Some(&texture),
+            0,
+        );
+
+        let depth_buffer = if depth {
+            let depth_buffer = context.create_renderbuffer().unwrap();
+            context.bind_renderbuffer(gl::RENDERBUFFER, Some(&depth_buffer));
+            context.framebuffer_renderbuffer(
+                gl::FRAMEBUFFER,
+                gl::DEPTH_ATTACHMENT,
+                gl::RENDERBUFFER,
+                Some(&depth_buffer),
+            );
+            Some(depth_buffer)
+        } else {
+            None
+        };
+        context.bind_framebuffer(gl::FRAMEBUFFER, None);
+
+        let mut target = RenderTarget {
+            framebuffer,
+            texture,
+            depth_buffer,
+            width: 0,
+            height: 0,
+        };
+        target.resize(context, width, height);
+        target
+    }
+
+    /// Reallocates the target's storage if its size has changed.
+    pub fn resize(&mut self, context: &WebGLRenderingContext, width: i32, height: i32) {
+        if (width, height) == (self.width, self.height) {
+            return;
+        }
+        self.width = width;
+        self.height = height;
+
+        context.bind_texture(gl::TEXTURE_2D, Some(&self.texture));
+        context.tex_image2_d(
+            gl::TEXTURE_2D,
+            0,
+            gl::RGBA as i32,
+            width,
+            height,
+            0,
+            gl::RGBA,
+            gl::UNSIGNED_BYTE,
+            None::<&[u8]>,
+        );
+        if let Some(ref depth_buffer) = self.depth_buffer {
+            context.bind_renderbuffer(gl::RENDERBUFFER, Some(depth_buffer));
+            context.renderbuffer_storage(gl::RENDERBUFFER, gl::DEPTH_COMPONENT16, width, height);
+        }
+    }
+
+    /// Binds the target for drawing and sets the viewport to cover it.
+    pub fn bind(&self, context: &WebGLRenderingContext) {
+        context.bind_framebuffer(gl::FRAMEBUFFER, Some(&self.framebuffer));
+        context.viewport(0, 0, self.width, self.height);
+    }
+}
+
+/// Visualises overdraw: every fragment adds a fixed amount into an offscreen target with
+/// depth testing off, and the totals are then shown through a heat ramp.
+struct OverdrawView {
+    target: RenderTarget,
+    count_shader: Shader,
+    count_position: Option<u32>,
+    count_mvp_matrix: WebGLUniformLocation,
+    ramp_shader: Shader,
+    ramp_position: Option<u32>,
+    ramp_counts: WebGLUniformLocation,
+}
+
+impl OverdrawView {
+    pub fn new(context: &WebGLRenderingContext) -> Self {
+        let count_shader = Shader::new(
+            context,
+            include_str!("../shaders/overdraw_count.vert.glsl"),
+            include_str!("../shaders/overdraw_count.frag.glsl"),
+        );
+        let ramp_shader = Shader::new(
+            context,
+            include_str!("../shaders/fullscreen.vert.glsl"),
+            include_str!("../shaders/overdraw_ramp.frag.glsl"),
+        );
+        OverdrawView {
+            target: RenderTarget::new(context, 1, 1, false),
+            count_position: attrib_location(context, &count_shader.program, "position"),
+            count_mvp_matrix: context
+                .get_uniform_location(&count_shader.program, "mvpMatrix")
+                .unwrap(),
+            count_shader,
+            ramp_position: attrib_location(context, &ramp_shader.program, "position"),
+            ramp_counts: context
+                .get_uniform_location(&ramp_shader.program, "counts")
+                .unwrap(),
+            ramp_shader,
+        }
+    }
+
+    /// Matches the offscreen target to the canvas size.
+    pub fn resize(&mut self, context: &WebGLRenderingContext, width: i32, height: i32) {
+        self.target.resize(context, width, height);
+    }
+
+    /// Renders the overdraw of `meshes` (each with its model matrix) to the canvas.
+    pub fn render(
+        &self,
+        context: &WebGLRenderingContext,
+        quad: &FullScreenQuad,
+        view_proj_matrix: &Matrix4<f32>,
+        meshes: &[(&BoundMesh, Matrix4<f32>)],
+    ) {
+        self.target.bind(context);
+        context.clear_color(0., 0., 0., 0.);
+        context.clear(gl::COLOR_BUFFER_BIT);
+
+        context.disable(gl::DEPTH_TEST);
+        context.enable(gl::BLEND);
+        context.blend_func(gl::ONE, gl::ONE);
+        context.use_program(Some(&self.count_shader.program));
+        if let Some(position) = self.count_position {
+            context.enable_vertex_attrib_array(position);
+        }
+        for &(mesh, ref model) in meshes {
+            debug_assert!(mesh.belongs_to(context), "mesh drawn in another context");
+            let mvp_matrix = view_proj_matrix * model;
+            context.uniform_matrix4fv(
+                Some(&self.count_mvp_matrix),
+                false,
+                &(mvp_matrix.as_ref() as &[f32; 16])[..],
+            );
+            if let Some(position) = self.count_position {
+                mesh.point_positions(position);
+            }
+            mesh.draw(gl::TRIANGLES);
+        }
+        if let Some(position) = self.count_position {
+            context.disable_vertex_attrib_array(position);
+        }
+        context.disable(gl::BLEND);
+
+        context.bind_framebuffer(gl::FRAMEBUFFER, None);
+        context.viewport(0, 0, self.target.width, self.target.height);
+        context.use_program(Some(&self.ramp_shader.program));
+        context.active_texture(gl::TEXTURE0);
+        context.bind_texture(gl::TEXTURE_2D, Some(&self.target.texture));
+        context.uniform1i(Some(&self.ramp_counts), 0);
+        quad.draw(context, self.ramp_position);
+        context.enable(gl::DEPTH_TEST);
+    }
+}
+
+/// Looks up a vertex attribute, returning `None` (rather than GL's -1) if the program doesn't
+/// have it - e.g. because the shader never reads it and the compiler optimized it out.
+fn attrib_location(
+    context: &WebGLRenderingContext,
+    program: &WebGLProgram,
+    name: &str,
+) -> Option<u32> {
+    let location = context.get_attrib_location(program, name);
+    if location < 0 {
+        None
+    } else {
+        Some(location as u32)
+    }
+}
+
+/// Describes the WebGL implementation, for including in bug reports.  The unmasked renderer
+/// and vendor are only available where `WEBGL_debug_renderer_info` is exposed.
+fn webgl_info(context: &WebGLRenderingContext) -> Vec<(&'static str, String)> {
+    let parameter = |name: u32| -> String {
+        js!( return String(@{context}.getParameter(@{name})); )
+            .try_into()
+            .unwrap_or_default()
+    };
+    let mut info = vec![
+        ("Version", parameter(gl::VERSION)),
+        ("Shading language", parameter(gl::SHADING_LANGUAGE_VERSION)),
+        ("Vendor", parameter(gl::VENDOR)),
+        ("Renderer", parameter(gl::RENDERER)),
+    ];
+    if has_extension(context, "WEBGL_debug_renderer_info") {
+        let unmasked: Result<Vec<String>, _> = js! {
+            var gl = @{context};
+            var ext = gl.getExtension("WEBGL_debug_renderer_info");
+            return [
+                String(gl.getParameter(ext.UNMASKED_VENDOR_WEBGL)),
+                String(gl.getParameter(ext.UNMASKED_RENDERER_WEBGL))
+            ];
+        }.try_into();
+        if let Ok(unmasked) = unmasked {
+            info.push(("Unmasked vendor", unmasked[0].clone()));
+            info.push(("Unmasked renderer", unmasked[1].clone()));
+        }
+    }
+    info
+}
+
+/// How clicks are resolved to objects in the scene.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum PickBackend {
+    /// Intersect a ray through the cursor with the CPU-side meshes.
+    Ray,
+    /// Render object IDs offscreen and read back the pixel under the cursor; see `IdBuffer`.
+    ColorId,
+}
+
+/// Color-ID picking: every object is drawn flat into an offscreen target in a color encoding
+/// its index, so reading back the pixel under the cursor identifies exactly what's visible
+/// there, whatever the geometry.  The catch is that `read_pixels` has to wait for the GPU to
+/// finish all queued work, stalling the pipeline, so this is only done on click.
+struct IdBuffer {
+    target: RenderTarget,
+    shader: Shader,
+    position: Option<u32>,
+    mvp_matrix: WebGLUniformLocation,
+    id: WebGLUniformLocation,
+}
+
+impl IdBuffer {
+    pub fn new(context: &WebGLRenderingContext) -> Self {
+        let shader = Shader::new(
+            context,
+            include_str!("../shaders/overdraw_count.vert.glsl"),
+            include_str!("../shaders/pick.frag.glsl"),
+        );
+        IdBuffer {
+            target: RenderTarget::new(context, 1, 1, true),
+            position: attrib_location(context, &shader.program, "position"),
+            mvp_matrix: context
+                .get_uniform_location(&shader.program, "mvpMatrix")
+                .unwrap(),
+            id: context.get_uniform_location(&shader.program, "id").unwrap(),
+            shader,
+        }
+    }
+
+    /// Returns the index into `meshes` of the object covering pixel (`x`, `y`) of a
+    /// `width`x`height` canvas (origin top-left), if any.
+    pub fn pick(
+        &mut self,
+        context: &WebGLRenderingContext,
+        view_proj_matrix: &Matrix4<f32>,
+        meshes: &[(&BoundMesh, Matrix4<f32>)],
+        (width, height): (i32, i32),
+        (x, y): (i32, i32),
+    ) -> Option<usize> {
+        self.target.resize(context, width, height);
+        self.target.bind(context);
+        context.clear_color(0., 0., 0., 0.);
+        context.clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+
+        context.use_program(Some(&self.shader.program));
+        if let Some(position) = self.position {
+            context.enable_vertex_attrib_array(position);
+        }
+        for (i, &(mesh, ref model)) in meshes.iter().enumerate() {
+            debug_assert!(mesh.belongs_to(context), "mesh drawn in another context");
+            // 0 is left for the background, so IDs start at 1.
+            let id = i + 1;
+            context.uniform4f(
+                Some(&self.id),
+                (id & 0xff) as f32 / 255.,
+                ((id >> 8) & 0xff) as f32 / 255.,
+                ((id >> 16) & 0xff) as f32 / 255.,
+                1.,
+            );
+            let mvp_matrix = view_proj_matrix * model;
+            context.uniform_matrix4fv(
+                Some(&self.mvp_matrix),
+                false,
+                &(mvp_matrix.as_ref() as &[f32; 16])[..],
+            );
+            if let Some(position) = self.position {
+                mesh.point_positions(position);
+            }
+            mesh.draw(gl::TRIANGLES);
+        }
+        if let Some(position) = self.position {
+            context.disable_vertex_attrib_array(position);
+        }
+
+        let pixel = js! {
+            var pixel = new Uint8Array(4);
+            @{context}.readPixels(
+                @{x}, @{height - 1 - y}, 1, 1, @{gl::RGBA}, @{gl::UNSIGNED_BYTE}, pixel
+            );
+            return pixel;
+        };
+        context.bind_framebuffer(gl::FRAMEBUFFER, None);
+
+        let pixel: Vec<u8> = pixel.try_into().ok().map(|p: TypedArray<u8>| p.to_vec())?;
+        let id = pixel[0] as usize | (pixel[1] as usize) << 8 | (pixel[2] as usize) << 16;
+        id.checked_sub(1)
+    }
+}
+
+/// Returns whether the named WebGL extension is available (enabling it as a side effect).
+/// The generated bindings don't include any extensions, so this goes via JavaScript.
+fn has_extension(context: &WebGLRenderingContext, name: &str) -> bool {
+    let supported = js! {
+        return @{context}.getExtension(@{name}) !== null;
+    };
+    supported.try_into().unwrap_or(false)
+}
+
+fn document_hidden() -> bool {
+    let hidden = js! {
+        return document.hidden === true;
+    };
+    hidden.try_into().unwrap_or(false)
+}
+
+/// Renders the scene's depth from the light's point of view into a depth texture - the first
+/// step towards shadow mapping.  Needs `WEBGL_depth_texture`.
+struct ShadowMap {
+    size: i32,
+    framebuffer: WebGLFramebuffer,
+    pub depth_texture: WebGLTexture,
+    shader: Shader,
+    position: Option<u32>,
+    light_matrix_uniform: WebGLUniformLocation,
+    m_matrix: WebGLUniformLocation,
+    /// Projection * view transform from world space into the light's clip space, as used by
+    /// the most recent `render`.
+    pub light_matrix: Matrix4<f32>,
+}
+
+impl ShadowMap {
+    pub fn new(context: &WebGLRenderingContext, size: i32) -> Option<Self> {
+        if !has_extension(context, "WEBGL_depth_texture") {
+            console!(log, "WEBGL_depth_texture unavailable - shadow map disabled");
+            return None;
+        }
+
+        let depth_texture = context.create_texture().unwrap();
+        context.bind_texture(gl::TEXTURE_2D, Some(&depth_texture));
+        context.tex_parameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32);
+        context.tex_parameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32);
+        context.tex_parameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+        context.tex_parameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+        context.tex_image2_d(
+            gl::TEXTURE_2D,
+            0,
+            gl::DEPTH_COMPONENT as i32,
+            size,
+            size,
+            0,
+            gl::DEPTH_COMPONENT,
+            gl::UNSIGNED_INT,
+            None::<&[u8]>,
+        );
+
+        let framebuffer = context.create_framebuffer().unwrap();
+        context.bind_framebuffer(gl::FRAMEBUFFER, Some(&framebuffer));
+        context.framebuffer_texture2_d(
+            gl::FRAMEBUFFER,
+            gl::DEPTH_ATTACHMENT,
+            gl::TEXTURE_2D,
+            Some(&depth_texture),
+            0,
+        );
+        context.bind_framebuffer(gl::FRAMEBUFFER, None);
+
+        let shader = Shader::new(
+            context,
+            include_str!("../shaders/depth.vert.glsl"),
+            include_str!("../shaders/depth.frag.glsl"),
+        );
+        let position = attrib_location(context, &shader.program, "position");
+        let light_matrix_uniform = context
+            .get_uniform_location(&shader.program, "lightMatrix")
+            .unwrap();
+        let m_matrix = context
+            .get_uniform_location(&shader.program, "Mmatrix")
+            .unwrap();
+
+        Some(ShadowMap {
+            size,
+            framebuffer,
+            depth_texture,
+            shader,
+            position,
+            light_matrix_uniform,
+            m_matrix,
+            light_matrix: Matrix4::from_scale(1.),
+        })
+    }
+
+    /// Renders the depth of each `(mesh, model matrix)` pair as seen from `light_position`
+    /// looking at the origin.  Leaves the default framebuffer bound, but the viewport is the
+    /// caller's to restore.
+    pub fn render(
+        &mut self,
+        context: &WebGLRenderingContext,
+        light_position: Point3<f32>,
+        polygon_offset: (f32, f32),
+        meshes: &[(&BoundMesh, Matrix4<f32>)],
+    ) {
+        let light_projection: Matrix4<f32> = PerspectiveFov {
+            fovy: Deg(90.).into(),
+            aspect: 1.,
+            near: 1.,
+            far: 50.,
+        }.into();
+        let light_view =
+            Matrix4::look_at(light_position, Point3::new(0., 0., 0.), vec3(0., 1., 0.));
+        self.light_matrix = light_projection * light_view;
+
+        context.bind_framebuffer(gl::FRAMEBUFFER, Some(&self.framebuffer));
+        context.viewport(0, 0, self.size, self.size);
+        context.clear(gl::DEPTH_BUFFER_BIT);
+
+        // Push the stored depths back a little so surfaces don't shadow themselves.
+        context.enable(gl::POLYGON_OFFSET_FILL);
+        context.polygon_offset(polygon_offset.0, polygon_offset.1);
+
+        context.use_program(Some(&self.shader.program));
+        context.uniform_matrix4fv(
+            Some(&self.light_matrix_uniform),
+            false,
+            &(self.light_matrix.as_ref() as &[f32; 16])[..],
+        );
+        if let Some(position) = self.position {
+            context.enable_vertex_attrib_array(position);
+        }
+        for &(mesh, ref model) in meshes {
+            debug_assert!(mesh.belongs_to(context), "mesh drawn in another context");
+            if let Some(position) = self.position {
+                mesh.point_positions(position);
+            }
+            context.uniform_matrix4fv(
+                Some(&self.m_matrix),
+                false,
+                &(model.as_ref() as &[f32; 16])[..],
+            );
+            mesh.draw(gl::TRIANGLES);
+        }
+        if let Some(position) = self.position {
+            context.disable_vertex_attrib_array(position);
+        }
+
+        context.disable(gl::POLYGON_OFFSET_FILL);
+        context.bind_framebuffer(gl::FRAMEBUFFER, None);
+    }
+}
+
+/// Displays a depth texture as greyscale, for debugging the shadow map.
+struct DepthView {
+    shader: Shader,
+    position: Option<u32>,
+    depth_map: WebGLUniformLocation,
+}
+
+impl DepthView {
+    pub fn new(context: &WebGLRenderingContext) -> Self {
+        let shader = Shader::new(
+            context,
+            include_str!("../shaders/fullscreen.vert.glsl"),
+            include_str!("../shaders/depth_view.frag.glsl"),
+        );
+        let position = attrib_location(context, &shader.program, "position");
+        let depth_map = context
+            .get_uniform_location(&shader.program, "depthMap")
+            .unwrap();
+        DepthView {
+            shader,
+            position,
+            depth_map,
+        }
+    }
+
+    pub fn draw(
+        &self,
+        context: &WebGLRenderingContext,
+        quad: &FullScreenQuad,
+        texture: &WebGLTexture,
+    ) {
+        context.use_program(Some(&self.shader.program));
+        context.active_texture(gl::TEXTURE0);
+        context.bind_texture(gl::TEXTURE_2D, Some(texture));
+        context.uniform1i(Some(&self.depth_map), 0);
+        quad.draw(context, self.position);
+    }
+}
+
+/// Low resolutions the scene can be rendered at for a retro look, cycled through at runtime.
+/// `None` renders straight to the canvas at full resolution.
+const PIXEL_ART_PRESETS: &[Option<(u32, u32)>] =
+    &[None, Some((320, 240)), Some((160, 120)), Some((640, 480))];
+
+/// Renders the scene into a small offscreen target and scales it up to the canvas with
+/// nearest-neighbor filtering, giving chunky pixels.  The offscreen target isn't multisampled,
+/// so this also does away with the canvas's antialiasing.
+struct PixelArtView {
+    target: RenderTarget,
+    shader: Shader,
+    position: Option<u32>,
+    image: WebGLUniformLocation,
+}
+
+impl PixelArtView {
+    pub fn new(context: &WebGLRenderingContext) -> Self {
+        let target = RenderTarget::new(context, 1, 1, true);
+        context.bind_texture(gl::TEXTURE_2D, Some(&target.texture));
+        context.tex_parameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32);
+        context.tex_parameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32);
+
+        let shader = Shader::new(
+            context,
+            include_str!("../shaders/fullscreen.vert.glsl"),
+            include_str!("../shaders/blit.frag.glsl"),
+        );
+        let position = attrib_location(context, &shader.program, "position");
+        let image = context.get_uniform_location(&shader.program, "image").unwrap();
+        PixelArtView {
+            target,
+            shader,
+            position,
+            image,
+        }
+    }
+
+    /// Directs subsequent drawing into the offscreen target at the given resolution.
+    pub fn bind(&mut self, context: &WebGLRenderingContext, width: u32, height: u32) {
+        self.target.resize(context, width as i32, height as i32);
+        self.target.bind(context);
+    }
+
+    /// Scales what was drawn since `bind` up to fill the canvas.
+    pub fn present(
+        &self,
+        context: &WebGLRenderingContext,
+        quad: &FullScreenQuad,
+        width: u32,
+        height: u32,
+    ) {
+        context.bind_framebuffer(gl::FRAMEBUFFER, None);
+        context.viewport(0, 0, width as i32, height as i32);
+        context.disable(gl::DEPTH_TEST);
+        context.use_program(Some(&self.shader.program));
+        context.active_texture(gl::TEXTURE0);
+        context.bind_texture(gl::TEXTURE_2D, Some(&self.target.texture));
+        context.uniform1i(Some(&self.image), 0);
+        quad.draw(context, self.position);
+        context.enable(gl::DEPTH_TEST);
+    }
+}
+
+/// How many recent frames the `#stats` frame rate is averaged over.
+const STATS_FRAMES: usize = 30;
+
+/// How many recent frames `FrameGraph` shows.
+const FRAME_GRAPH_LENGTH: usize = 120;
+/// Height of the frame graph's bars, in pixels per millisecond.
+const FRAME_GRAPH_SCALE: f32 = 2.;
+
+/// A scrolling bar graph of recent frame times, drawn in the bottom-left corner of the canvas
+/// with scissored clears, so it needs no shader or buffers.  Lines mark 60 and 30 frames per
+/// second; bars are green, yellow or red depending on which of those they reach.
+struct FrameGraph {
+    times: VecDeque<f32>,
+}
+
+impl FrameGraph {
+    pub fn new() -> Self {
+        FrameGraph {
+            times: VecDeque::with_capacity(FRAME_GRAPH_LENGTH),
+        }
+    }
+
+    /// Records a frame that took `dt` milliseconds.
+    pub fn push(&mut self, dt: f32) {
+        if self.times.len() == FRAME_GRAPH_LENGTH {
+            self.times.pop_front();
+        }
+        self.times.push_back(dt);
+    }
+
+    /// Draws the graph over whatever is in the color buffer.  Leaves the clear color black.
+    pub fn draw(&self, context: &WebGLRenderingContext) {
+        const BAR_WIDTH: i32 = 2;
+        const MARGIN: i32 = 8;
+        let max_ms = 50.;
+        let height = (max_ms * FRAME_GRAPH_SCALE) as i32;
+        let fill = |x: i32, y: i32, w: i32, h: i32, color: [f32; 4]| {
+            context.scissor(x, y, w, h);
+            context.clear_color(color[0], color[1], color[2], color[3]);
+            context.clear(gl::COLOR_BUFFER_BIT);
+        };
+
+        context.enable(gl::SCISSOR_TEST);
+        fill(MARGIN, MARGIN, FRAME_GRAPH_LENGTH as i32 * BAR_WIDTH, height, [0., 0., 0., 1.]);
+        for (i, &dt) in self.times.iter().enumerate() {
+            let color = if dt <= 1000. / 60. {
+                [0.2, 0.8, 0.2, 1.]
+            } else if dt <= 1000. / 30. {
+                [0.9, 0.8, 0.1, 1.]
+            } else {
+                [0.9, 0.2, 0.1, 1.]
+            };
+            let bar = (dt.min(max_ms) * FRAME_GRAPH_SCALE).max(1.) as i32;
+            fill(MARGIN + i as i32 * BAR_WIDTH, MARGIN, BAR_WIDTH, bar, color);
+        }
+        for &ms in &[1000. / 60., 1000. / 30.] {
+            let y = MARGIN + (ms * FRAME_GRAPH_SCALE) as i32;
+            let width = FRAME_GRAPH_LENGTH as i32 * BAR_WIDTH;
+            fill(MARGIN, y, width, 1, [1., 1., 1., 1.]);
+        }
+        context.disable(gl::SCISSOR_TEST);
+        context.clear_color(0., 0., 0., 1.);
+    }
+}
+
+/// `(top, bottom)` colors of the backgrounds cycled through with B.  Presets with the same
+/// color top and bottom are solid, and are just cleared to rather than drawn.
+const BACKGROUND_PRESETS: &[([f32; 3], [f32; 3])] = &[
+    ([0., 0., 0.], [0., 0., 0.]),
+    ([0.05, 0.07, 0.18], [0.05, 0.07, 0.18]),
+    ([0.25, 0.35, 0.6], [0.05, 0.05, 0.1]),
+    ([0.9, 0.6, 0.35], [0.2, 0.1, 0.15]),
+    ([0.6, 0.6, 0.6], [0.15, 0.15, 0.15]),
+];
+
+/// Fills the viewport with a vertical gradient, drawn before the scene.
+struct GradientBackground {
+    shader: Shader,
+    position: Option<u32>,
+    top_color: WebGLUniformLocation,
+    bottom_color: WebGLUniformLocation,
+    dither: WebGLUniformLocation,
+}
+
+impl GradientBackground {
+    pub fn new(context: &WebGLRenderingContext) -> Self {
+        let shader = Shader::new(
+            context,
+            include_str!("../shaders/fullscreen.vert.glsl"),
+            include_str!("../shaders/gradient.frag.glsl"),
+        );
+        let position = attrib_location(context, &shader.program, "position");
+        let top_color = context
+            .get_uniform_location(&shader.program, "topColor")
+            .unwrap();
+        let bottom_color = context
+            .get_uniform_location(&shader.program, "bottomColor")
+            .unwrap();
+        let dither = context
+            .get_uniform_location(&shader.program, "dither")
+            .unwrap();
+        GradientBackground {
+            shader,
+            position,
+            top_color,
+            bottom_color,
+            dither,
+        }
+    }
+
+    pub fn draw(
+        &self,
+        context: &WebGLRenderingContext,
+        quad: &FullScreenQuad,
+        top: [f32; 3],
+        bottom: [f32; 3],
+        dither: bool,
+    ) {
+        context.use_program(Some(&self.shader.program));
+        context.uniform3f(Some(&self.top_color), top[0], top[1], top[2]);
+        context.uniform3f(Some(&self.bottom_color), bottom[0], bottom[1], bottom[2]);
+        context.uniform1i(Some(&self.dither), dither as i32);
+
+        // The background must never occlude the scene.
+        context.disable(gl::DEPTH_TEST);
+        context.depth_mask(false);
+        quad.draw(context, self.position);
+        context.depth_mask(true);
+        context.enable(gl::DEPTH_TEST);
+    }
+}
+
+/// A DOM overlay showing short lines of status text.  Each line is keyed so that independent
+/// features can update or remove their own line without disturbing the others.
+struct Hud {
+    container: Element,
+    lines: Vec<(&'static str, Element)>,
+}
+
+impl Hud {
+    pub fn new(container: Element) -> Self {
+        Hud {
+            container,
+            lines: Vec::new(),
+        }
+    }
+
+    pub fn set(&mut self, key: &'static str, text: &str) {
+        if let Some(&(_, ref line)) = self.lines.iter().find(|&&(k, _)| k == key) {
+            line.set_text_content(text);
+            return;
+        }
+        let line = document().create_element("div").unwrap();
+        line.set_text_content(text);
+        self.container.append_child(&line);
+        self.lines.push((key, line));
+    }
+
+    pub fn clear(&mut self, key: &'static str) {
+        if let Some(index) = self.lines.iter().position(|&(k, _)| k == key) {
+            let (_, line) = self.lines.remove(index);
+            let _ = self.container.remove_child(&line);
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Tonemap {
+    Reinhard = 0,
+    Aces = 1,
+}
+
+/// A further canvas showing the scene from its own camera; see `Settings::views`.
+struct View {
+    canvas: CanvasElement,
+    camera: Camera,
+}
+
+/// The turntable demo in progress (see `State::start_turntable`), with everything it changed
+/// to put back afterwards.
+struct Turntable {
+    /// Frame timestamp the turn started at, once the first frame has run.
+    start_time: Option<f64>,
+    /// Model rotation the turn starts from and returns to.
+    start_transform: Matrix4<f32>,
+    /// Frames saved so far.
+    saved: u32,
+    view: Camera,
+    top_color: [f32; 3],
+    bottom_color: [f32; 3],
+    axes: Option<BoundMesh>,
+    measure_points: Vec<Point3<f32>>,
+    measure_line: Option<BoundMesh>,
+    frame_graph: Option<FrameGraph>,
+    split: Option<f32>,
+    show_shadow_map: bool,
+    show_overdraw: bool,
+    wireframe: bool,
+    normal_display: NormalDisplay,
+}
+
+/// Debug view replacing shading with the surface normal as a color.
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum NormalDisplay {
+    Off = 0,
+    /// The normals as stored in the mesh.
+    Model = 1,
+    /// The normals after the model's normal matrix.
+    World = 2,
+}
+
+bitflags! {
+    struct Keys: u8 {
+        const UP    = 0b0000_0001;
+        const DOWN  = 0b0000_0010;
+        const LEFT  = 0b0000_0100;
+        const RIGHT = 0b0000_1000;
+        const LIGHT_CCW = 0b0001_0000;
+        const LIGHT_CW  = 0b0010_0000;
+        const LIGHT_UP   = 0b0100_0000;
+        const LIGHT_DOWN = 0b1000_0000;
+    }
+}
+
+const ROTATION_KEYS: [Keys; 4] = [Keys::UP, Keys::DOWN, Keys::LEFT, Keys::RIGHT];
+
+/// The key codes (`KeyboardEvent.code`) that hold down each of the `Keys` flags, so they can be
+/// moved somewhere comfortable on layouts other than QWERTY.  A flag can have several keys.
+struct KeyBindings(Vec<(Keys, &'static str)>);
+
+impl KeyBindings {
+    /// The flags bound to `code`, if any.
+    fn lookup(&self, code: &str) -> Keys {
+        self.0
+            .iter()
+            .filter(|&&(_, bound)| bound == code)
+            .fold(Keys::empty(), |keys, &(key, _)| keys | key)
+    }
+}
+
+impl Default for KeyBindings {
+    /// WASD to rotate the model, and comma/period or the arrow keys to move the light.
+    fn default() -> Self {
+        KeyBindings(vec![
+            (Keys::LEFT, "KeyA"),
+            (Keys::UP, "KeyW"),
+            (Keys::DOWN, "KeyS"),
+            (Keys::RIGHT, "KeyD"),
+            (Keys::LIGHT_CCW, "Comma"),
+            (Keys::LIGHT_CW, "Period"),
+            (Keys::LIGHT_CCW, "ArrowLeft"),
+            (Keys::LIGHT_CW, "ArrowRight"),
+            (Keys::LIGHT_UP, "ArrowUp"),
+            (Keys::LIGHT_DOWN, "ArrowDown"),
+        ])
+    }
+}
+
+/// The most lights the scene shader can take.  Must match `MAX_LIGHTS` in scene.frag.glsl.
+const MAX_LIGHTS: usize = 4;
+
+/// A light shining equally in all directions from `position`, in world space.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct PointLight {
+    pub position: Point3<f32>,
+    /// Linear RGB intensity; white `[1, 1, 1]` matches the main light.
+    pub color: [f32; 3],
+}
+
+/// Extra lights toggled with 7: warm from the left and cool from the right, so their
+/// contributions are easy to tell apart where they overlap.
+const DEMO_LIGHTS: [PointLight; 2] = [
+    PointLight {
+        position: Point3 { x: -6., y: 3., z: 2. },
+        color: [0.9, 0.35, 0.1],
+    },
+    PointLight {
+        position: Point3 { x: 6., y: 3., z: 2. },
+        color: [0.1, 0.35, 0.9],
+    },
+];
+
+struct Settings {
+    /// Radians of rotation per pixel of mouse drag (after the response curve).
+    mouse_sensitivity: f32,
+    /// Exponent of the power curve applied to mouse-drag deltas. 1 is linear; larger values
+    /// give finer control for small movements while still allowing fast large sweeps.
+    mouse_exponent: f32,
+    /// Width and height of the shadow map's depth texture.
+    shadow_map_size: i32,
+    /// Slope-scaled factor and constant units passed to `polygon_offset` when rendering the
+    /// shadow map.
+    shadow_depth_bias: (f32, f32),
+    /// Depth tolerance (in light clip space, scaled to [0, 1]) when comparing a fragment
+    /// against the shadow map, to avoid shadow acne.
+    shadow_bias: f32,
+    /// Radius of the circle (around the vertical axis) the light orbits on.
+    light_orbit_radius: f32,
+    /// Initial height of the light's orbit above the origin.
+    light_orbit_height: f32,
+    /// Speed, in units per second, at which the up/down arrow keys raise and lower the light.
+    light_height_speed: f32,
+    /// Length of each simulation step in `State::step`, in milliseconds.
+    fixed_timestep: f32,
+    /// Most time, in milliseconds, that can be waiting to be simulated; anything beyond it
+    /// (e.g. after a stall) is dropped rather than caught up on.
+    max_accumulated_time: f32,
+    /// Phong exponent for specular highlights; higher values give smaller, sharper ones.
+    shininess: f32,
+    /// Initial share of each surface's color lit regardless of the lights, from 0 (pure
+    /// diffuse shading) to 1 (unlit); the lights' diffuse term makes up the rest.
+    ambient: f32,
+    /// Color of that fill light.
+    ambient_color: [f32; 3],
+    /// Color of the edges drawn over objects with a wire overlay; see `SceneNode::wire_overlay`.
+    wire_overlay_color: [f32; 3],
+    /// Initial diameter, in pixels, of a point cloud's points.
+    point_size: f32,
+    /// Draw points as circles rather than squares.
+    round_points: bool,
+    /// Keys that rotate the model and move the light while held.  These take precedence over
+    /// the other shortcuts, should they clash.
+    key_bindings: KeyBindings,
+    /// Orbit speed, in radians per second, when moved by key or orbiting automatically.
+    light_orbit_speed: f32,
+    /// Skip redrawing frames when nothing has changed since the last one.  Input marks the
+    /// state dirty, and continuous animation (held rotation keys, auto light orbit) keeps it
+    /// perpetually dirty, so this only saves work while the view is idle.
+    redraw_only_when_dirty: bool,
+    /// Milliseconds a rotation key must be held before it starts rotating, so accidental
+    /// taps don't nudge the model.  0 rotates straight away.
+    key_hold_threshold: f32,
+    /// Pixels the mouse must move with the button down before a drag starts rotating.  0
+    /// rotates on the first movement.
+    drag_dead_zone: i32,
+    /// Stop scheduling frames while the page is hidden (e.g. in a background tab) to save
+    /// battery, resuming when it becomes visible again.
+    pause_when_hidden: bool,
+    /// Milliseconds without input after which the frame loop drops to `idle_frame_rate`, or
+    /// `None` to always run at full rate.  Animation keeps playing, just less smoothly.
+    idle_timeout: Option<f32>,
+    /// Frames per second to run at while idle.
+    idle_frame_rate: f32,
+    /// How long the turntable demo takes to turn the model once around, in milliseconds; see
+    /// `State::start_turntable`.
+    turntable_duration: f32,
+    /// How many frames the turntable demo saves over its turn.
+    turntable_frames: u32,
+    /// Average output luminance auto-exposure steers towards (roughly middle gray once
+    /// displayed).
+    auto_exposure_target: f32,
+    /// How quickly auto-exposure adapts: the fraction (in log space) of the remaining error
+    /// corrected per second.
+    auto_exposure_speed: f32,
+    /// Render the main view with reversed-Z depth (near at 1, far at 0, compared with
+    /// `GEQUAL`).  The precision win relies on a floating-point depth buffer and a [0, 1]
+    /// clip-space depth range; WebGL gives neither (depth is typically 24-bit fixed point and
+    /// clip z spans [-1, 1]), so here it mostly shifts where precision is spent rather than
+    /// adding any.
+    reversed_z: bool,
+    /// Which technique to use when clicking to pick an object.
+    pick_backend: PickBackend,
+    /// Largest rotation, in radians, that held keys can apply in a single step, as a backstop
+    /// should `fixed_timestep` be set very long.
+    max_frame_rotation: f32,
+    /// Build the ziggurat procedurally from `(tiers, base_size, tier_ratio)` (see `Ziggurat`)
+    /// instead of loading the baked model.
+    procedural_ziggurat: Option<(u32, f32, f32)>,
+    /// GPU storage for the peon's colors; see `ColorFormat`.
+    peon_color_format: ColorFormat,
+    /// How the peon reflects light; see `Finish`.  The ziggurat stays matte stone.
+    peon_finish: Finish,
+    /// Draw a ground plane under the ziggurat with this many subdivisions (see `Plane`).
+    ground_plane: Option<u32>,
+    /// Draw the ground plane as a triangle strip; see `Plane::triangle_strip`.
+    ground_plane_strip: bool,
+    /// Image to texture the ground plane with, relative to the page.
+    ground_texture: Option<&'static str>,
+    /// Anisotropic filtering level for textures, sharpening them at grazing angles, or `None`
+    /// for the most the GPU supports (and 1 for none).  Clamped to what it supports, and
+    /// ignored without `EXT_texture_filter_anisotropic`.
+    texture_anisotropy: Option<f32>,
+    /// Recenter a loaded PLY ziggurat and scale it to fit a unit cube (see
+    /// `PlyMesh::normalize`), for models of unknown size.
+    normalize_model: bool,
+    /// Load a PLY ziggurat flat-shaded, with a normal per face rather than per vertex.
+    flat_shading: bool,
+    /// Move the camera in or out at startup to fit the ziggurat in view, whatever the scale of
+    /// the model.  Otherwise it starts 6 units away.
+    frame_model: bool,
+    /// Further canvases showing the same scene, each given by CSS selector with the eye
+    /// position of its own camera, which looks at the origin and is framed like the main one.
+    ///
+    /// WebGL contexts can't share buffers, textures or shaders, so a context per canvas would
+    /// need every mesh and texture uploaded again and kept in step.  Instead each view is
+    /// rendered in the main canvas's context, just before the main view in the same animation
+    /// frame, and copied across with `drawImage`.  That costs a full scene render plus a copy
+    /// per view per frame, and the views can't get a frame callback of their own: whichever
+    /// ran last would leave its image on the main canvas.
+    views: &'static [(&'static str, [f32; 3])],
+    /// Put a cube either side of the ziggurat, each tinted a different color through
+    /// `BoundMesh::material_color`.
+    tinted_cubes: bool,
+    /// Length of the lines `State::toggle_axes` shows along each world axis.
+    axis_size: f32,
+    /// The grid shown with the axes to begin with, or `None` for none; see `State::grid`.
+    grid: Option<Grid>,
+    /// How the ziggurat's vertex attributes are laid out; see `VertexLayout`.
+    ziggurat_layout: VertexLayout,
+    /// Where to fetch the models from, relative to the page.  The ziggurat can also be an OBJ
+    /// file, told apart by its `.obj` extension.
+    peon_url: &'static str,
+    ziggurat_url: &'static str,
+    /// Model to show instead of the ziggurat if it fails to load at startup, loaded the same
+    /// way.  The ziggurat's error still shows in the HUD.
+    fallback_model_url: Option<&'static str>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            mouse_sensitivity: 0.01,
+            mouse_exponent: 1.,
+            shadow_map_size: 1024,
+            shadow_depth_bias: (2., 4.),
+            shadow_bias: 0.005,
+            light_orbit_radius: 6.,
+            light_orbit_height: 0.,
+            light_height_speed: 4.,
+            fixed_timestep: 16.,
+            max_accumulated_time: 250.,
+            shininess: 32.,
+            ambient: 0.5,
+            ambient_color: [1., 1., 1.],
+            wire_overlay_color: [0.1, 0.1, 0.1],
+            point_size: 3.,
+            round_points: true,
+            key_bindings: KeyBindings::default(),
+            light_orbit_speed: 1.,
+            redraw_only_when_dirty: false,
+            key_hold_threshold: 0.,
+            drag_dead_zone: 0,
+            pause_when_hidden: true,
+            idle_timeout: None,
+            idle_frame_rate: 10.,
+            turntable_duration: 6000.,
+            turntable_frames: 60,
+            auto_exposure_target: 0.45,
+            auto_exposure_speed: 1.5,
+            reversed_z: false,
+            pick_backend: PickBackend::Ray,
+            max_frame_rotation: 0.1,
+            procedural_ziggurat: None,
+            peon_color_format: ColorFormat::Float,
+            peon_finish: Finish::Dielectric,
+            ground_plane: None,
+            ground_plane_strip: false,
+            ground_texture: None,
+            texture_anisotropy: None,
+            tinted_cubes: false,
+            axis_size: 2.,
+            grid: Some(Grid {
+                spacing: 0.25,
+                extent: 2.,
+                major_every: 4,
+            }),
+            normalize_model: false,
+            flat_shading: false,
+            frame_model: true,
+            views: &[],
+            ziggurat_layout: VertexLayout::Separate,
+            peon_url: "models/peon.ply",
+            ziggurat_url: "models/ziggurat.ply",
+            fallback_model_url: None,
+        }
+    }
+}
+
+/// A half-line from `origin` along the unit vector `direction`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Ray {
+    pub origin: Point3<f32>,
+    pub direction: Vector3<f32>,
+}
+
+/// A perspective camera looking from `eye` towards `target`, converting between world
+/// coordinates and screen coordinates - pixels from the top left of a `(width, height)`
+/// viewport.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Camera {
+    pub eye: Point3<f32>,
+    pub target: Point3<f32>,
+    pub up: Vector3<f32>,
+    /// Vertical field of view, in degrees.
+    pub fovy: f32,
+    pub near: f32,
+    pub far: f32,
+}
+
+impl Camera {
+    pub fn view_matrix(&self) -> Matrix4<f32> {
+        Matrix4::look_at(self.eye, self.target, self.up)
+    }
+
+    pub fn projection_matrix(&self, aspect: f32) -> Matrix4<f32> {
+        PerspectiveFov {
+            fovy: Deg(self.fovy).into(),
+            aspect,
+            near: self.near,
+            far: self.far,
+        }.into()
+    }
+
+    /// How far the eye is from the point it's looking at.
+    pub fn distance(&self) -> f32 {
+        self.eye.distance(self.target)
+    }
+
+    /// Moves the eye `factor` times closer to the target, staying between the clip planes so
+    /// the target never gets clipped.
+    pub fn zoom(&mut self, factor: f32) {
+        let distance = self.distance();
+        if distance <= 0. || factor <= 0. {
+            return;
+        }
+        let zoomed = (distance / factor).max(self.near).min(self.far / 2.);
+        self.eye = self.target + (self.eye - self.target) * (zoomed / distance);
+    }
+
+    /// Moves the eye towards or away from the target until a sphere around the target
+    /// enclosing the `(min, max)` box just fills the view vertically, so the box stays in view
+    /// whichever way it's turned.  The clip planes are scaled by the same factor, keeping
+    /// their ratio (and so the depth precision) unchanged.
+    pub fn frame(&mut self, (min, max): (Point3<f32>, Point3<f32>)) {
+        let mut radius = 0f32;
+        for &x in &[min.x, max.x] {
+            for &y in &[min.y, max.y] {
+                for &z in &[min.z, max.z] {
+                    radius = radius.max(Point3::new(x, y, z).distance(self.target));
+                }
+            }
+        }
+        let distance = self.distance();
+        if radius <= 0. || distance <= 0. {
+            return;
+        }
+        let scale = radius / (self.fovy.to_radians() / 2.).sin() / distance;
+        self.eye = self.target + (self.eye - self.target) * scale;
+        self.near *= scale;
+        self.far *= scale;
+    }
+
+    fn view_projection_matrix(&self, (width, height): (f32, f32)) -> Matrix4<f32> {
+        self.projection_matrix(width / height) * self.view_matrix()
+    }
+
+    /// Projects a world-space point onto the screen, returning its pixel coordinates in `x`
+    /// and `y` and its normalized device depth (-1 at the near plane, 1 at the far) in `z`.
+    /// Points behind the camera have no projection.
+    pub fn project(&self, world: Point3<f32>, viewport: (f32, f32)) -> Option<Vector3<f32>> {
+        let clip = self.view_projection_matrix(viewport) * world.to_homogeneous();
+        if clip.w <= 0. {
+            return None;
+        }
+        let ndc = clip.truncate() / clip.w;
+        Some(vec3(
+            (ndc.x + 1.) * 0.5 * viewport.0,
+            (1. - ndc.y) * 0.5 * viewport.1,
+            ndc.z,
+        ))
+    }
+
+    /// Returns the world-space ray from the near plane through the given screen pixel.
+    pub fn unproject(&self, (x, y): (f32, f32), viewport: (f32, f32)) -> Option<Ray> {
+        let ndc_x = 2. * x / viewport.0 - 1.;
+        let ndc_y = 1. - 2. * y / viewport.1;
+        let inverse = self.view_projection_matrix(viewport).invert()?;
+        let near = Point3::from_homogeneous(inverse * vec4(ndc_x, ndc_y, -1., 1.));
+        let far = Point3::from_homogeneous(inverse * vec4(ndc_x, ndc_y, 1., 1.));
+        Some(Ray {
+            origin: near,
+            direction: (far - near).normalize(),
+        })
+    }
+}
+
+/// A camera pose relative to the model, for bookmarking and sharing views.  Its `Display` form
+/// is a query string (`eye=x,y,z&target=x,y,z&up=x,y,z&fov=degrees&distance=d`) that `parse`
+/// accepts, so a dumped view can be restored by appending it to the page URL.
+#[derive(Debug, PartialEq)]
+struct CameraSnippet {
+    pub eye: Point3<f32>,
+    pub target: Point3<f32>,
+    pub up: Vector3<f32>,
+    /// Vertical field of view, in degrees.
+    pub fovy: f32,
+    pub distance: f32,
+}
+
+impl CameraSnippet {
+    pub fn parse(query: &str) -> Option<Self> {
+        fn parse_triple(value: &str) -> Option<(f32, f32, f32)> {
+            let mut parts = value.split(',').map(|part| part.trim().parse::<f32>().ok());
+            match (parts.next(), parts.next(), parts.next(), parts.next()) {
+                (Some(Some(x)), Some(Some(y)), Some(Some(z)), None) => Some((x, y, z)),
+                _ => None,
+            }
+        }
+
+        let (mut eye, mut target, mut up, mut fovy, mut distance) = (None, None, None, None, None);
+        for pair in query.trim_start_matches('?').split('&') {
+            let mut pair = pair.splitn(2, '=');
+            match (pair.next(), pair.next()) {
+                (Some("eye"), Some(value)) => eye = parse_triple(value),
+                (Some("target"), Some(value)) => target = parse_triple(value),
+                (Some("up"), Some(value)) => up = parse_triple(value),
+                (Some("fov"), Some(value)) => fovy = value.parse().ok(),
+                (Some("distance"), Some(value)) => distance = value.parse().ok(),
+                _ => {}
+            }
+        }
+        let (eye, target, up) = (eye?, target?, up?);
+        Some(CameraSnippet {
+            eye: Point3::new(eye.0, eye.1, eye.2),
+            target: Point3::new(target.0, target.1, target.2),
+            up: vec3(up.0, up.1, up.2),
+            fovy: fovy?,
+            distance: distance?,
+        })
+    }
+}
+
+impl fmt::Display for CameraSnippet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            concat!(
+                "eye={:.4},{:.4},{:.4}&target={:.4},{:.4},{:.4}&",
+                "up={:.4},{:.4},{:.4}&fov={}&distance={:.4}"
+            ),
+            self.eye.x,
+            self.eye.y,
+            self.eye.z,
+            self.target.x,
+            self.target.y,
+            self.target.z,
+            self.up.x,
+            self.up.y,
+            self.up.z,
+            self.fovy,
+            self.distance
+        )
+    }
+}
+
+/// Where the peon stands in the ziggurat's model space: on top of it, at the center.
+const PEON_OFFSET: Vector3<f32> = Vector3 {
+    x: 0.,
+    y: 1.,
+    z: 0.,
+};
+
+/// Half the width of the ground plane, enough to leave a margin around the ziggurat.
+const GROUND_PLANE_SIZE: f32 = 12.;
+
+/// How close (in CSS pixels) a press must be to the split view's divider to grab it.
+const SPLIT_GRAB_DISTANCE: f32 = 6.;
+
+/// Vertex index labels are only shown for meshes at most this big - beyond that they're
+/// unreadable and creating the DOM nodes gets slow.
+const MAX_LABELLED_VERTICES: usize = 200;
+
+/// Maps a raw mouse delta (in pixels) through a sign-preserving power curve.
+fn response_curve(delta: f32, exponent: f32) -> f32 {
+    delta.signum() * delta.abs().powf(exponent)
+}
+
+/// An event, as queued by its handler for the next frame to apply.
+enum Input {
+    KeyDown { code: String, repeat: bool },
+    KeyUp(String),
+    Blur,
+    /// Left button pressed over the canvas, in client and canvas coordinates.
+    MouseDown { x: i32, y: i32, offset_x: f32 },
+    MouseUp,
+    /// Canvas coordinates, in CSS pixels.
+    Click { x: f32, y: f32 },
+    /// Mouse moved over the canvas, in canvas coordinates.
+    Hover { x: f32, y: f32 },
+    /// Mouse moved anywhere in the window, in client coordinates, by `dx`, `dy`.
+    MouseMove { x: i32, y: i32, dx: i32, dy: i32 },
+    /// Fingers touching, moving on or leaving the canvas: just the ones that changed.
+    TouchStart(Vec<TouchPoint>),
+    TouchMove(Vec<TouchPoint>),
+    TouchEnd(Vec<TouchPoint>),
+    /// Something changed off-frame, such as a texture finishing loading.
+    Redraw,
+    /// The ziggurat finished reloading; see `State::reload_ziggurat`.
+    ZigguratLoaded(Result<Vec<ModelPart>, ModelError>),
+}
+
+/// A finger on the canvas, in client coordinates.
+#[derive(Clone, Copy)]
+struct TouchPoint {
+    /// Stays the same for as long as the finger stays down, telling fingers apart.
+    id: i32,
+    x: f32,
+    y: f32,
+}
+
+impl TouchPoint {
+    fn from_touches(touches: Vec<Touch>) -> Vec<TouchPoint> {
+        touches
+            .iter()
+            .map(|touch| TouchPoint {
+                id: touch.identifier(),
+                x: touch.client_x() as f32,
+                y: touch.client_y() as f32,
+            })
+            .collect()
+    }
+
+    fn distance(&self, other: &TouchPoint) -> f32 {
+        ((self.x - other.x).powi(2) + (self.y - other.y).powi(2)).sqrt()
+    }
+}
+
+/// Input waiting for the next frame.  Event handlers only ever touch this, never `State`:
+/// a frame holds the state mutably borrowed while it runs, so a handler borrowing it too
+/// could panic.  Plain `Cell`s can't be left borrowed, so this can't.
+#[derive(Default)]
+struct InputQueue {
+    events: Cell<Vec<Input>>,
+    /// Whether the frame loop stopped because the page was hidden; see
+    /// `Settings::pause_when_hidden`.  Kept here so the handler that restarts it can tell.
+    paused: Cell<bool>,
+}
+
+impl InputQueue {
+    fn push(&self, input: Input) {
+        let mut events = self.events.take();
+        events.push(input);
+        self.events.set(events);
+    }
+
+    fn take(&self) -> Vec<Input> {
+        self.events.take()
+    }
+}
+
+struct State {
+    time_old: f64,
+    settings: Settings,
+    /// The viewer, on the z axis looking at the origin.  It stays put while the scene rotates
+    /// in front of it; `camera` gives the equivalent camera orbiting the model instead.
+    view: Camera,
+    /// Further canvases showing the scene from their own cameras; see `Settings::views`.
+    views: Vec<View>,
+    /// Aspect ratio to project with instead of the canvas's, while rendering one of `views`.
+    render_aspect: Option<f32>,
+    canvas: CanvasElement,
+    context: WebGLRenderingContext,
+    shader: Shader,
+    /// Mesh attribute locations in `shader`, `None` where the shader doesn't use them.
+    position: Option<u32>,
+    normal: Option<u32>,
+    color: Option<u32>,
+    /// Enabled per mesh by `BoundMesh::point_alphas`, rather than with the other attributes.
+    alpha: Option<u32>,
+    /// Likewise, by `BoundMesh::point_texcoords`.
+    uv: Option<u32>,
+    p_matrix: WebGLUniformLocation,
+    v_matrix: WebGLUniformLocation,
+    m_matrix: WebGLUniformLocation,
+    n_matrix: WebGLUniformLocation,
+    normal_display_uniform: WebGLUniformLocation,
+    normal_display: NormalDisplay,
+    affine_color: WebGLUniformLocation,
+    affine_interpolation: bool,
+    /// Locations of each element of the shader's light arrays, up to `MAX_LIGHTS`.
+    light_positions: Vec<WebGLUniformLocation>,
+    light_colors: Vec<WebGLUniformLocation>,
+    num_lights: WebGLUniformLocation,
+    view_pos: WebGLUniformLocation,
+    shininess: WebGLUniformLocation,
+    light_matrix: WebGLUniformLocation,
+    shadows_uniform: WebGLUniformLocation,
+    shadow_map_uniform: WebGLUniformLocation,
+    shadow_bias: WebGLUniformLocation,
+    shadow_texel_size: WebGLUniformLocation,
+    shadows: bool,
+    exposure_uniform: WebGLUniformLocation,
+    tonemap_operator: WebGLUniformLocation,
+    exposure: f32,
+    tonemap: Tonemap,
+    dither_uniform: WebGLUniformLocation,
+    color_scale_uniform: WebGLUniformLocation,
+    /// Global RGB multiplier applied to every fragment's base color, for quick warm/cool and
+    /// brightness grading of the whole scene.
+    color_scale: Vector3<f32>,
+    ambient_uniform: WebGLUniformLocation,
+    ambient_color: WebGLUniformLocation,
+    wire_overlay_uniform: WebGLUniformLocation,
+    wire_color: WebGLUniformLocation,
+    /// Share of the base color that's ambient; see `Settings::ambient`.
+    ambient: f32,
+    point_size_uniform: WebGLUniformLocation,
+    round_points: WebGLUniformLocation,
+    /// Diameter of point clouds' points, in pixels.
+    point_size: f32,
+    /// Continuously adjust `exposure` to the brightness of the rendered image.
+    auto_exposure: bool,
+    /// Add sub-LSB noise to the final colors to hide banding.
+    dither: bool,
+    object_alpha: WebGLUniformLocation,
+    material_color: WebGLUniformLocation,
+    specular_color: WebGLUniformLocation,
+    textured: WebGLUniformLocation,
+    base_texture: WebGLUniformLocation,
+    /// Opacity of each of `scene_meshes`, by index; missing entries are opaque.
+    object_opacity: Vec<f32>,
+    /// Index into `scene_meshes` of the last object clicked on.
+    selected: Option<usize>,
+    /// The scene itself, as opposed to debug overlays: the ziggurat, with the peon (and
+    /// optional ground plane) as children.  Its root transform is the model rotation.
+    scene: SceneNode,
+    /// How many of the ziggurat's parts `scene` holds: the root, then the rest as its first
+    /// children.
+    ziggurat_parts: usize,
+    peon_mesh: Box<dyn Mesh>,
+    labels_container: Element,
+    vertex_labels: Vec<Element>,
+    hud: Hud,
+    marker: BoundMesh,
+    measuring: bool,
+    /// Picked points in model space, so they stay attached to the model as it rotates.
+    measure_points: Vec<Point3<f32>>,
+    measure_line: Option<BoundMesh>,
+    /// Whether hovering reports the vertex color under the cursor.
+    color_picker: bool,
+    /// Draw meshes' triangle edges instead of filling them.
+    wireframe: bool,
+    /// Shown when `Some`, in which case every frame is drawn so the graph keeps scrolling.
+    frame_graph: Option<FrameGraph>,
+    /// World axis lines (and grid), when shown; see `axis_gizmo`.
+    axes: Option<BoundMesh>,
+    /// The grid drawn with the axes, adjustable with the keyboard.  `axes` is only rebuilt
+    /// when it changes.
+    grid: Option<Grid>,
+    /// The page's `#stats` element, showing the frame rate and last frame time.
+    stats: Element,
+    /// The last `STATS_FRAMES` frame times, in milliseconds.
+    frame_times: VecDeque<f32>,
+    light_position: Point3<f32>,
+    /// Lights besides the main one at `light_position`, which alone orbits and casts shadows.
+    extra_lights: Vec<PointLight>,
+    /// Angle of the light around its orbit, with 0 on the +Z axis.
+    light_angle: f32,
+    /// Height of the light's orbit above the origin.
+    light_height: f32,
+    light_auto_orbit: bool,
+    /// Elapsed time not yet simulated, in milliseconds; see `Settings::fixed_timestep`.
+    accumulator: f32,
+    quad: FullScreenQuad,
+    shadow_map: Option<ShadowMap>,
+    depth_view: DepthView,
+    show_shadow_map: bool,
+    overdraw: OverdrawView,
+    show_overdraw: bool,
+    background: GradientBackground,
+    background_preset: usize,
+    top_color: [f32; 3],
+    bottom_color: [f32; 3],
+    /// Taken out while picking so the scene meshes can be borrowed alongside it.
+    id_buffer: Option<IdBuffer>,
+    pixel_art_view: PixelArtView,
+    /// Resolution to render the scene at before scaling it up to the canvas, if any.
+    pixel_art: Option<(u32, u32)>,
+    pixel_art_preset: usize,
+    keys: Keys,
+    prev_keys: Keys,
+    /// How long each of `ROTATION_KEYS` has been held, in milliseconds.
+    key_hold_times: [f32; 4],
+    dragging: bool,
+    /// Whether the current drag has left the dead zone around `drag_start`.
+    drag_active: bool,
+    drag_start: (i32, i32),
+    /// Where the divider of the split comparison view is, as a fraction of the canvas width;
+    /// `None` when the view isn't split.  See `split_normal_display`.
+    split: Option<f32>,
+    /// Whether the mouse is dragging the split view's divider rather than the model.
+    dragging_split: bool,
+    /// Fingers currently on the canvas, in the order they touched it.
+    touches: Vec<TouchPoint>,
+    /// Optional hook invoked at the end of every `animate`; see `start`.
+    on_frame: Option<Box<dyn FnMut(&State, f64)>>,
+    /// Set by anything that changes what's on screen; see `Settings::redraw_only_when_dirty`.
+    dirty: bool,
+    /// Save the next frame as a PNG once it's drawn; see `save_canvas`.
+    screenshot_requested: bool,
+    /// The turntable demo, while it's recording.
+    turntable: Option<Turntable>,
+    /// The ziggurat's bounding box in model space, for the turntable demo to frame.
+    model_bounds: Option<(Point3<f32>, Point3<f32>)>,
+    /// Canvas backing size at the last redraw, so resizes force a redraw.
+    drawn_size: (u32, u32),
+    /// Shared with the event handlers, which queue their input here rather than borrowing the
+    /// state; see `InputQueue`.
+    input: Rc<InputQueue>,
+    /// Frame timestamp of the most recent input; see `Settings::idle_timeout`.
+    last_input: f64,
+}
+
+impl State {
+    fn toggle_vertex_labels(&mut self) {
+        if !self.vertex_labels.is_empty() {
+            for label in self.vertex_labels.drain(..) {
+                let _ = self.labels_container.remove_child(&label);
+            }
+            return;
+        }
+
+        let num_vertices = self.peon_mesh.vertices().len() / 3;
+        if num_vertices > MAX_LABELLED_VERTICES {
+            console!(log, format!(
+                "Not labelling vertices: mesh has {} (limit {})",
+                num_vertices, MAX_LABELLED_VERTICES
+            ));
+            return;
+        }
+        for index in 0..num_vertices {
+            let label = document().create_element("div").unwrap();
+            label.class_list().add("label").unwrap();
+            label.set_text_content(&index.to_string());
+            self.labels_container.append_child(&label);
+            self.vertex_labels.push(label);
+        }
+    }
+
+    fn update_vertex_labels(&self) {
+        let (camera, viewport) = (self.camera(), self.css_viewport());
+        for (label, vertex) in self.vertex_labels.iter().zip(self.peon_mesh.vertices().chunks(3)) {
+            let position = Point3::new(vertex[0], vertex[1], vertex[2]) + PEON_OFFSET;
+            let style = match camera.project(position, viewport) {
+                Some(screen) => format!("left: {}px; top: {}px", screen.x, screen.y),
+                None => "display: none".to_string(),
+            };
+            label.set_attribute("style", &style).unwrap();
+        }
+    }
+
+    fn projection_matrix(&self) -> Matrix4<f32> {
+        let (w, h) = (self.canvas.width(), self.canvas.height());
+        let aspect = self.render_aspect.unwrap_or((w as f32) / (h as f32));
+        let projection = self.view.projection_matrix(aspect);
+        if self.settings.reversed_z {
+            // Negating clip-space z maps the near plane to depth 1 and the far plane to 0.
+            Matrix4::from_nonuniform_scale(1., 1., -1.) * projection
+        } else {
+            projection
+        }
+    }
+
+    /// The current view as a camera in the model's coordinate space.
+    fn camera(&self) -> Camera {
+        let snippet = self.camera_snippet();
+        Camera {
+            eye: snippet.eye,
+            target: snippet.target,
+            up: snippet.up,
+            fovy: snippet.fovy,
+            near: self.view.near,
+            far: self.view.far,
+        }
+    }
+
+    /// Describes the current view as a camera orbiting the (unrotated) model.
+    fn camera_snippet(&self) -> CameraSnippet {
+        let to_model = self.scene.transform.invert().unwrap();
+        let distance = self.view.distance();
+        CameraSnippet {
+            eye: to_model.transform_point(Point3::new(0., 0., distance)),
+            target: to_model.transform_point(Point3::new(0., 0., 0.)),
+            up: to_model.transform_vector(vec3(0., 1., 0.)),
+            fovy: self.view.fovy,
+            distance,
+        }
+    }
+
+    /// Logs the current view (and copies it to the clipboard where allowed) in a form that
+    /// `apply_camera_snippet` or the page's query string can restore.
+    fn dump_camera(&self) {
+        let snippet = self.camera_snippet().to_string();
+        console!(log, format!("Camera: ?{}", snippet));
+        js! {
+            if (navigator.clipboard) {
+                navigator.clipboard.writeText(@{snippet}).catch(function() {});
+            }
+        }
+    }
+
+    /// Serializes the peon mesh as it currently stands (after any brightness/gamma tweaks) and
+    /// has the browser download it.
+    fn export_peon(&self) {
+        let mut ply = Vec::new();
+        write_ascii_ply(&*self.peon_mesh, &mut ply).unwrap();
+        let ply = String::from_utf8(ply).unwrap();
+        js! {
+            var blob = new Blob([@{ply}], { type: "application/octet-stream" });
+            var link = document.createElement("a");
+            link.href = URL.createObjectURL(blob);
+            link.download = "peon.ply";
+            link.click();
+            URL.revokeObjectURL(link.href);
+        }
+    }
+
+    /// Has the browser download the canvas as a PNG called `name`.  This must run straight
+    /// after `render`, before the frame is handed to the compositor: the context isn't created
+    /// with `preserveDrawingBuffer` (which would cost a copy every frame), so the drawing
+    /// buffer may read back blank any later.
+    fn save_canvas(&self, name: &str) {
+        let url = match self.canvas.to_data_url(Some("image/png"), None) {
+            Ok(url) => url,
+            Err(_) => {
+                console!(error, "Couldn't read back the canvas");
+                return;
+            }
+        };
+        js! {
+            var link = document.createElement("a");
+            link.href = @{url};
+            link.download = @{name};
+            link.click();
+        }
+    }
+
+    fn apply_camera_snippet(&mut self, snippet: &CameraSnippet) {
+        let back = (snippet.eye - snippet.target).normalize();
+        let right = snippet.up.cross(back).normalize();
+        let up = back.cross(right);
+        // The rows of the model rotation are the camera's axes in model space.
+        let rotation = Matrix4::from(Matrix3::from_cols(right, up, back).transpose());
+        self.scene.transform = rotation * Matrix4::from_translation(-snippet.target.to_vec());
+        self.view.eye = Point3::new(0., 0., snippet.distance);
+        self.view.fovy = snippet.fovy;
+        self.dirty = true;
+    }
+
+    /// The canvas size in CSS pixels, as used by mouse events.
+    fn css_viewport(&self) -> (f32, f32) {
+        (self.canvas.offset_width() as f32, self.canvas.offset_height() as f32)
+    }
+
+    /// Converts a point on the canvas (in CSS pixels) into a ray in the peon's model space.
+    fn pick_ray(&self, x: f32, y: f32) -> Option<Ray> {
+        let ray = self.camera().unproject((x, y), self.css_viewport())?;
+        Some(Ray {
+            origin: ray.origin - PEON_OFFSET,
+            ..ray
+        })
+    }
+
+    fn cycle_normal_display(&mut self) {
+        self.normal_display = match self.normal_display {
+            NormalDisplay::Off => NormalDisplay::World,
+            NormalDisplay::World => NormalDisplay::Model,
+            NormalDisplay::Model => NormalDisplay::Off,
+        };
+        match self.normal_display {
+            NormalDisplay::Off => self.hud.clear("normals"),
+            NormalDisplay::Model => self.hud.set("normals", "Normals: model space"),
+            NormalDisplay::World => self.hud.set("normals", "Normals: world space"),
+        }
+    }
+
+    fn toggle_affine_interpolation(&mut self) {
+        self.affine_interpolation = !self.affine_interpolation;
+        if self.affine_interpolation {
+            self.hud.set("interpolation", "Color interpolation: affine");
+        } else {
+            self.hud.clear("interpolation");
+        }
+    }
+
+    fn cycle_background(&mut self) {
+        self.background_preset = (self.background_preset + 1) % BACKGROUND_PRESETS.len();
+        let (top, bottom) = BACKGROUND_PRESETS[self.background_preset];
+        self.top_color = top;
+        self.bottom_color = bottom;
+    }
+
+    /// Re-uploads `peon_mesh` after it has been changed.
+    fn rebind_peon(&mut self) {
+        let mut peon = self
+            .peon_mesh
+            .bind_with(&self.context, self.settings.peon_color_format);
+        peon.set_finish(self.settings.peon_finish);
+        if let Some(node) = self.scene.find_mut("peon") {
+            node.mesh = peon;
+        }
+    }
+
+    fn adjust_peon_brightness(&mut self, factor: f32) {
+        self.peon_mesh = Box::new(self.peon_mesh.adjust_brightness(factor));
+        self.rebind_peon();
+    }
+
+    fn adjust_peon_gamma(&mut self, gamma: f32) {
+        self.peon_mesh = Box::new(self.peon_mesh.adjust_gamma(gamma));
+        self.rebind_peon();
+    }
+
+    /// Switches the peon between a dielectric finish and gold.
+    fn toggle_peon_finish(&mut self) {
+        let (finish, name) = match self.settings.peon_finish {
+            Finish::Dielectric => (Finish::Metal(vec3(1., 0.78, 0.34)), "gold"),
+            Finish::Metal(_) => (Finish::Dielectric, "dielectric"),
+        };
+        self.settings.peon_finish = finish;
+        if let Some(node) = self.scene.find_mut("peon") {
+            node.mesh.set_finish(finish);
+        }
+        self.hud.set("finish", &format!("Peon finish: {}", name));
+    }
+
+    fn toggle_measuring(&mut self) {
+        self.measuring = !self.measuring;
+        self.measure_points.clear();
+        self.measure_line = None;
+        if self.measuring {
+            self.hud.set("measure", "Measure: click two points");
+        } else {
+            self.hud.clear("measure");
+        }
+    }
+
+    fn measure_click(&mut self, x: f32, y: f32) {
+        if self.measure_points.len() == 2 {
+            self.measure_points.clear();
+            self.measure_line = None;
+            self.hud.set("measure", "Measure: click two points");
+            return;
+        }
+
+        let hit = self.pick_ray(x, y).and_then(|ray| {
+            intersect_ray(&*self.peon_mesh, ray.origin, ray.direction)
+                .map(|hit| ray.origin + ray.direction * hit.t)
+        });
+        if let Some(point) = hit {
+            self.measure_points.push(point);
+        }
+
+        if self.measure_points.len() == 2 {
+            let (a, b) = (self.measure_points[0], self.measure_points[1]);
+            let line = OwnedMesh {
+                vertices: vec![a.x, a.y, a.z, b.x, b.y, b.z],
+                normals: vec![0., 0., 1., 0., 0., 1.],
+                colors: vec![1., 1., 0., 1., 1., 0.],
+                alphas: Vec::new(),
+                texcoords: Vec::new(),
+                indices: vec![0, 1],
+                wide_indices: Vec::new(),
+            };
+            self.measure_line = Some(line.bind(&self.context));
+            self.hud.set("measure", &format!("Distance: {:.3}", a.distance(b)));
+        }
+    }
+
+    fn toggle_frame_graph(&mut self) {
+        self.frame_graph = match self.frame_graph {
+            Some(_) => None,
+            None => Some(FrameGraph::new()),
+        };
+        self.dirty = true;
+    }
+
+    fn toggle_axes(&mut self) {
+        self.axes = match self.axes {
+            Some(_) => None,
+            None => {
+                let gizmo = axis_gizmo(self.settings.axis_size, self.grid);
+                Some(gizmo.bind(&self.context))
+            }
+        };
+        self.dirty = true;
+    }
+
+    /// Changes the grid, rebuilding the axes if they're shown.  The spacing is kept within
+    /// the extent, and coarse enough for at most `MAX_GRID_STEPS` lines.
+    fn adjust_grid<F: FnOnce(&mut Grid)>(&mut self, change: F) {
+        if let Some(ref mut grid) = self.grid {
+            change(grid);
+            grid.extent = grid.extent.max(0.1).min(1000.);
+            let finest = grid.extent / MAX_GRID_STEPS as f32;
+            grid.spacing = grid.spacing.max(finest).min(grid.extent);
+            grid.major_every = grid.major_every.min(100);
+            let text = format!(
+                "Grid: spacing {}, extent {}, major every {}",
+                grid.spacing, grid.extent, grid.major_every
+            );
+            self.hud.set("grid", &text);
+        }
+        if self.axes.is_some() {
+            let gizmo = axis_gizmo(self.settings.axis_size, self.grid);
+            self.axes = Some(gizmo.bind(&self.context));
+        }
+        self.dirty = true;
+    }
+
+    /// Adds or removes `DEMO_LIGHTS`.
+    fn toggle_demo_lights(&mut self) {
+        if self.extra_lights.is_empty() {
+            self.extra_lights = DEMO_LIGHTS.to_vec();
+            self.hud.set("lights", "Demo lights: on");
+        } else {
+            self.extra_lights.clear();
+            self.hud.clear("lights");
+        }
+        self.dirty = true;
+    }
+
+    fn toggle_wireframe(&mut self) {
+        self.wireframe = !self.wireframe;
+        self.dirty = true;
+    }
+
+    fn toggle_color_picker(&mut self) {
+        self.color_picker = !self.color_picker;
+        if self.color_picker {
+            self.hud.set("color_picker", "Color: hover over the peon");
+        } else {
+            self.hud.clear("color_picker");
+        }
+    }
+
+    /// Shows the peon's interpolated vertex color under the canvas point (`x`, `y`), before any
+    /// lighting, exposure or gamma is applied.
+    fn pick_color(&mut self, x: f32, y: f32) {
+        let color = self.pick_ray(x, y).and_then(|ray| {
+            intersect_ray(&*self.peon_mesh, ray.origin, ray.direction)
+                .and_then(|hit| hit.color(&*self.peon_mesh))
+        });
+        let text = match color {
+            Some(color) => {
+                let byte = |c: f32| (c.max(0.).min(1.) * 255.).round() as u8;
+                format!(
+                    "Color: #{:02x}{:02x}{:02x} ({:.3}, {:.3}, {:.3})",
+                    byte(color.x), byte(color.y), byte(color.z), color.x, color.y, color.z
+                )
+            }
+            None => "Color: hover over the peon".to_string(),
+        };
+        self.hud.set("color_picker", &text);
+    }
+
+    fn cycle_pixel_art(&mut self) {
+        self.pixel_art_preset = (self.pixel_art_preset + 1) % PIXEL_ART_PRESETS.len();
+        self.pixel_art = PIXEL_ART_PRESETS[self.pixel_art_preset];
+        match self.pixel_art {
+            Some((w, h)) => self.hud.set("pixel_art", &format!("Pixel art: {}x{}", w, h)),
+            None => self.hud.clear("pixel_art"),
+        }
+    }
+
+    fn adjust_exposure(&mut self, factor: f32) {
+        self.exposure *= factor;
+        self.show_exposure();
+    }
+
+    fn toggle_tonemap(&mut self) {
+        self.tonemap = match self.tonemap {
+            Tonemap::Reinhard => Tonemap::Aces,
+            Tonemap::Aces => Tonemap::Reinhard,
+        };
+        self.show_exposure();
+    }
+
+    fn toggle_auto_exposure(&mut self) {
+        self.auto_exposure = !self.auto_exposure;
+        if self.auto_exposure {
+            self.hud.set("auto_exposure", "Auto exposure");
+        } else {
+            self.hud.clear("auto_exposure");
+        }
+    }
+
+    /// Samples the frame just rendered and nudges `exposure` so its average luminance moves
+    /// towards `Settings::auto_exposure_target`.  Only a sparse grid of pixels is read back,
+    /// since `read_pixels` stalls until the GPU has finished the frame.
+    fn adapt_exposure(&mut self, dt: f32) {
+        let luminance: f64 = js! {
+            var gl = @{&self.context};
+            var width = gl.drawingBufferWidth;
+            var height = gl.drawingBufferHeight;
+            var pixel = new Uint8Array(4);
+            var samples = 8;
+            var sum = 0;
+            for (var i = 0; i < samples; i++) {
+                for (var j = 0; j < samples; j++) {
+                    var x = Math.floor((i + 0.5) * width / samples);
+                    var y = Math.floor((j + 0.5) * height / samples);
+                    gl.readPixels(x, y, 1, 1, gl.RGBA, gl.UNSIGNED_BYTE, pixel);
+                    var l = (0.2126 * pixel[0] + 0.7152 * pixel[1] + 0.0722 * pixel[2]) / 255;
+                    sum += Math.log(Math.max(l, 1 / 255));
+                }
+            }
+            return Math.exp(sum / (samples * samples));
+        }.try_into()
+        .unwrap_or(0.);
+        if luminance <= 0. {
+            return;
+        }
+
+        let error = (self.settings.auto_exposure_target / luminance as f32).ln();
+        let step = (self.settings.auto_exposure_speed * dt * 0.001).min(1.);
+        self.exposure *= (error * step).exp();
+        if error.abs() > 0.01 {
+            // Keep rendering until we've settled.
+            self.dirty = true;
+            self.show_exposure();
+        }
+    }
+
+    fn show_exposure(&mut self) {
+        let text = format!("Exposure: {:.2} ({:?})", self.exposure, self.tonemap);
+        self.hud.set("exposure", &text);
+    }
+
+    /// Multiplies the global color scale component-wise by `factor`.
+    fn adjust_color_scale(&mut self, factor: Vector3<f32>) {
+        self.color_scale = self.color_scale.mul_element_wise(factor);
+        self.show_color_scale();
+    }
+
+    fn reset_color_scale(&mut self) {
+        self.color_scale = vec3(1., 1., 1.);
+        self.show_color_scale();
+    }
+
+    fn adjust_ambient(&mut self, delta: f32) {
+        self.ambient = (self.ambient + delta).max(0.).min(1.);
+        let text = format!("Ambient: {:.2}", self.ambient);
+        self.hud.set("ambient", &text);
+    }
+
+    /// Scales the point size by `factor`, keeping it within 1-64 pixels.  The context may cap
+    /// it lower still; see `gl::ALIASED_POINT_SIZE_RANGE`.
+    fn adjust_point_size(&mut self, factor: f32) {
+        self.point_size = (self.point_size * factor).max(1.).min(64.);
+        let text = format!("Point size: {:.1}", self.point_size);
+        self.hud.set("point_size", &text);
+    }
+
+    fn show_color_scale(&mut self) {
+        let scale = self.color_scale;
+        let text = format!("Color scale: {:.2}, {:.2}, {:.2}", scale.x, scale.y, scale.z);
+        self.hud.set("color_scale", &text);
+    }
+
+    fn upload_lighting(&self) {
+        let main_light = PointLight {
+            position: self.light_position,
+            color: [1., 1., 1.],
+        };
+        let lights: Vec<_> = iter::once(main_light)
+            .chain(self.extra_lights.iter().cloned())
+            .take(MAX_LIGHTS)
+            .collect();
+        self.context.uniform1i(Some(&self.num_lights), lights.len() as i32);
+        for (i, light) in lights.iter().enumerate() {
+            let (p, c) = (light.position, light.color);
+            self.context.uniform3f(Some(&self.light_positions[i]), p.x, p.y, p.z);
+            self.context.uniform3f(Some(&self.light_colors[i]), c[0], c[1], c[2]);
+        }
+        let eye = self.view.eye;
+        self.context.uniform3f(Some(&self.view_pos), eye.x, eye.y, eye.z);
+        self.context
+            .uniform1f(Some(&self.shininess), self.settings.shininess);
+        self.context.uniform1f(Some(&self.exposure_uniform), self.exposure);
+        self.context
+            .uniform1i(Some(&self.tonemap_operator), self.tonemap as i32);
+        self.context.uniform1i(Some(&self.dither_uniform), self.dither as i32);
+        let scale = self.color_scale;
+        self.context
+            .uniform3f(Some(&self.color_scale_uniform), scale.x, scale.y, scale.z);
+        self.context.uniform1f(Some(&self.ambient_uniform), self.ambient);
+        self.context
+            .uniform1f(Some(&self.point_size_uniform), self.point_size);
+        let ambient = self.settings.ambient_color;
+        self.context
+            .uniform3f(Some(&self.ambient_color), ambient[0], ambient[1], ambient[2]);
+
+        match self.shadow_map {
+            Some(ref shadow_map) if self.shadows => {
+                self.context.uniform1i(Some(&self.shadows_uniform), 1);
+                self.context.uniform_matrix4fv(
+                    Some(&self.light_matrix),
+                    false,
+                    &(shadow_map.light_matrix.as_ref() as &[f32; 16])[..],
+                );
+                self.context.active_texture(gl::TEXTURE1);
+                self.context
+                    .bind_texture(gl::TEXTURE_2D, Some(&shadow_map.depth_texture));
+                self.context.uniform1i(Some(&self.shadow_map_uniform), 1);
+                self.context
+                    .uniform1f(Some(&self.shadow_bias), self.settings.shadow_bias);
+                self.context.uniform1f(
+                    Some(&self.shadow_texel_size),
+                    1. / self.settings.shadow_map_size as f32,
+                );
+                self.context.active_texture(gl::TEXTURE0);
+            }
+            _ => self.context.uniform1i(Some(&self.shadows_uniform), 0),
+        }
+    }
+
+    /// The meshes making up the scene itself (as opposed to debug overlays), each with its
+    /// model matrix.
+    fn scene_meshes(&self) -> Vec<(&BoundMesh, Matrix4<f32>)> {
+        let mut meshes = Vec::new();
+        self.scene.walk(Matrix4::identity(), &mut |node, world| {
+            meshes.push((&node.mesh, world))
+        });
+        meshes
+    }
+
+    /// Names of the entries of `scene_meshes`, for reporting picks.
+    fn scene_mesh_names(&self) -> Vec<&str> {
+        let mut names = Vec::new();
+        self.scene
+            .walk(Matrix4::identity(), &mut |node, _| names.push(&*node.name));
+        names
+    }
+
+    /// The peon's model matrix.  Its vertices, the measure points and `pick_ray` are all in
+    /// the peon's own model space.
+    fn peon_matrix(&self) -> Matrix4<f32> {
+        self.scene
+            .world_matrix("peon")
+            .unwrap_or(self.scene.transform)
+    }
+
+    /// Whether each of `scene_meshes` has a wire overlay; see `SceneNode::wire_overlay`.
+    fn scene_wire_overlays(&self) -> Vec<bool> {
+        let mut overlays = Vec::new();
+        self.scene
+            .walk(Matrix4::identity(), &mut |node, _| overlays.push(node.wire_overlay));
+        overlays
+    }
+
+    /// Finds which of `scene_meshes` is under the canvas point (`x`, `y`), in CSS pixels.
+    fn pick(&mut self, x: f32, y: f32) -> Option<usize> {
+        match self.settings.pick_backend {
+            PickBackend::Ray => {
+                let ray = self.pick_ray(x, y)?;
+                // Only the peon is kept on the CPU to intersect against.
+                intersect_ray(&*self.peon_mesh, ray.origin, ray.direction)?;
+                self.scene_mesh_names().iter().position(|&name| name == "peon")
+            }
+            PickBackend::ColorId => {
+                let (w, h) = (self.canvas.width() as i32, self.canvas.height() as i32);
+                let px = (x * w as f32 / self.canvas.offset_width() as f32) as i32;
+                let py = (y * h as f32 / self.canvas.offset_height() as f32) as i32;
+                let view_proj_matrix = self.projection_matrix() * self.view.view_matrix();
+                let mut id_buffer = self.id_buffer.take()?;
+                let picked = id_buffer.pick(
+                    &self.context,
+                    &view_proj_matrix,
+                    &self.scene_meshes(),
+                    (w, h),
+                    (px, py),
+                );
+                self.id_buffer = Some(id_buffer);
+                picked
+            }
+        }
+    }
+
+    fn pick_click(&mut self, x: f32, y: f32) {
+        self.selected = self.pick(x, y);
+        let name = self
+            .selected
+            .map_or("nothing", |i| self.scene_mesh_names()[i]);
+        let text = format!("Picked ({:?}): {}", self.settings.pick_backend, name);
+        self.hud.set("pick", &text);
+    }
+
+    fn opacity(&self, object: usize) -> f32 {
+        self.object_opacity.get(object).cloned().unwrap_or(1.)
+    }
+
+    /// Fades the selected object by `delta`, clamping its opacity to [0, 1].
+    fn adjust_selected_opacity(&mut self, delta: f32) {
+        let selected = match self.selected {
+            Some(selected) => selected,
+            None => {
+                self.hud.set("pick", "Click an object to select it first");
+                return;
+            }
+        };
+        if self.object_opacity.len() <= selected {
+            self.object_opacity.resize(selected + 1, 1.);
+        }
+        let opacity = (self.object_opacity[selected] + delta).max(0.).min(1.);
+        self.object_opacity[selected] = opacity;
+        let text = format!("{} opacity: {:.1}", self.scene_mesh_names()[selected], opacity);
+        self.hud.set("opacity", &text);
+    }
+
+    /// Turns the selected object's wire overlay on or off.
+    fn toggle_selected_wire_overlay(&mut self) {
+        let node = match self.selected.and_then(|selected| self.scene.nth_mut(selected)) {
+            Some(node) => node,
+            None => {
+                self.hud.set("pick", "Click an object to select it first");
+                return;
+            }
+        };
+        node.wire_overlay = !node.wire_overlay;
+        let text = format!(
+            "{} wire overlay: {}",
+            node.name,
+            if node.wire_overlay { "on" } else { "off" }
+        );
+        self.hud.set("wire_overlay", &text);
+        self.dirty = true;
+    }
+
+    fn toggle_pick_backend(&mut self) {
+        self.settings.pick_backend = match self.settings.pick_backend {
+            PickBackend::Ray => PickBackend::ColorId,
+            PickBackend::ColorId => PickBackend::Ray,
+        };
+        self.hud.set("pick", &format!("Picking: {:?}", self.settings.pick_backend));
+    }
+
+    fn enable_attribs(&self, attribs: &[Option<u32>]) {
+        for attrib in attribs.iter().filter_map(|&attrib| attrib) {
+            self.context.enable_vertex_attrib_array(attrib);
+        }
+    }
+
+    fn disable_attribs(&self, attribs: &[Option<u32>]) {
+        for attrib in attribs.iter().filter_map(|&attrib| attrib) {
+            self.context.disable_vertex_attrib_array(attrib);
+        }
+    }
+
+    fn draw_mesh(&self, mesh: &BoundMesh, model: &Matrix4<f32>, proj_matrix: &Matrix4<f32>) {
+        self.draw_mesh_as(gl::TRIANGLES, mesh, model, proj_matrix);
+    }
+
+    fn draw_mesh_as(
+        &self,
+        mode: u32,
+        mesh: &BoundMesh,
+        model: &Matrix4<f32>,
+        proj_matrix: &Matrix4<f32>,
+    ) {
+        self.set_up_mesh(mesh, model, proj_matrix);
+        // Wireframe mode draws the triangles' edges instead.
+        if mode == gl::TRIANGLES && self.wireframe && mesh.primitive != PrimitiveType::Points {
+            mesh.draw_edges();
+        } else {
+            mesh.draw(mode);
+        }
+    }
+
+    /// Draws the edges of a mesh's triangles in `Settings::wire_overlay_color`, over the mesh
+    /// itself; see `SceneNode::wire_overlay`.
+    fn draw_wire_overlay(
+        &self,
+        mesh: &BoundMesh,
+        model: &Matrix4<f32>,
+        proj_matrix: &Matrix4<f32>,
+    ) {
+        self.set_up_mesh(mesh, model, proj_matrix);
+        let color = self.settings.wire_overlay_color;
+        self.context
+            .uniform3f(Some(&self.wire_color), color[0], color[1], color[2]);
+        self.context.uniform1i(Some(&self.wire_overlay_uniform), 1);
+        mesh.draw_edges();
+        self.context.uniform1i(Some(&self.wire_overlay_uniform), 0);
+    }
+
+    /// Points the attributes at `mesh`'s buffers and uploads its uniforms, ready to draw it.
+    fn set_up_mesh(&self, mesh: &BoundMesh, model: &Matrix4<f32>, proj_matrix: &Matrix4<f32>) {
+        debug_assert!(mesh.belongs_to(&self.context), "mesh drawn in another context");
+        if let Some(position) = self.position {
+            mesh.point_positions(position);
+        }
+
+        if let Some(color) = self.color {
+            mesh.point_colors(color);
+        }
+
+        if let Some(normal) = self.normal {
+            mesh.point_normals(normal);
+        }
+
+        if let Some(alpha) = self.alpha {
+            mesh.point_alphas(alpha);
+        }
+
+        if let Some(uv) = self.uv {
+            mesh.point_texcoords(uv);
+        }
+        let tint = mesh.material_color;
+        self.context
+            .uniform3f(Some(&self.material_color), tint.x, tint.y, tint.z);
+        let specular = mesh.specular_color;
+        self.context
+            .uniform3f(Some(&self.specular_color), specular.x, specular.y, specular.z);
+        let round = self.settings.round_points && mesh.primitive == PrimitiveType::Points;
+        self.context.uniform1i(Some(&self.round_points), round as i32);
+        // Unit 2, clear of the shadow map on unit 1 and the scratch unit 0.
+        self.context.uniform1i(Some(&self.textured), mesh.is_textured() as i32);
+        if let Some(ref texture) = mesh.texture {
+            self.context.active_texture(gl::TEXTURE2);
+            self.context.bind_texture(gl::TEXTURE_2D, Some(texture));
+            self.context.uniform1i(Some(&self.base_texture), 2);
+            self.context.active_texture(gl::TEXTURE0);
+        }
+
+        self.context.uniform_matrix4fv(
+            Some(&self.p_matrix),
+            false,
+            &(proj_matrix.as_ref() as &[f32; 16])[..],
+        );
+        self.context.uniform_matrix4fv(
+            Some(&self.v_matrix),
+            false,
+            &(self.view.view_matrix().as_ref() as &[f32; 16])[..],
+        );
+        self.context.uniform_matrix4fv(
+            Some(&self.m_matrix),
+            false,
+            &(model.as_ref() as &[f32; 16])[..],
+        );
+        // The inverse transpose keeps normals perpendicular to surfaces under non-uniform
+        // scaling.
+        let normal_matrix =
+            Matrix3::from_cols(model.x.truncate(), model.y.truncate(), model.z.truncate())
+                .invert()
+                .map_or(Matrix3::identity(), |m| m.transpose());
+        self.context.uniform_matrix3fv(
+            Some(&self.n_matrix),
+            false,
+            &(normal_matrix.as_ref() as &[f32; 9])[..],
+        );
+    }
+
+    /// Forgets all held keys and any drag in progress.  Used when the window loses focus, since
+    /// the matching key-up/mouse-up events then go elsewhere and would never reach us.
+    fn release_input(&mut self) {
+        self.keys = Keys::empty();
+        self.prev_keys = Keys::empty();
+        self.key_hold_times = [0.; 4];
+        self.dragging = false;
+        self.touches.clear();
+        self.dirty = true;
+    }
+
+    /// Advances how long each rotation key has been held and returns those held for at least
+    /// `Settings::key_hold_threshold`.
+    fn held_keys(&mut self, dt: f32) -> Keys {
+        let mut held = Keys::empty();
+        for (hold_time, &key) in self.key_hold_times.iter_mut().zip(ROTATION_KEYS.iter()) {
+            if self.keys.contains(key) {
+                *hold_time += dt;
+                if *hold_time >= self.settings.key_hold_threshold {
+                    held |= key;
+                }
+            } else {
+                *hold_time = 0.;
+            }
+        }
+        held
+    }
+
+    fn toggle_split(&mut self) {
+        self.split = match self.split {
+            Some(_) => None,
+            None => Some(0.5),
+        };
+        self.dirty = true;
+    }
+
+    /// The normal display for the right-hand side of the split view: world-space normals
+    /// beside the lit model, or the lit model beside whichever normals are shown on the left.
+    fn split_normal_display(&self) -> NormalDisplay {
+        match self.normal_display {
+            NormalDisplay::Off => NormalDisplay::World,
+            _ => NormalDisplay::Off,
+        }
+    }
+
+    /// Starts dragging the split view's divider if the canvas point `x` (in CSS pixels) is on
+    /// it, returning whether it was.
+    fn start_split_drag(&mut self, x: f32) -> bool {
+        if let Some(split) = self.split {
+            let (width, _) = self.css_viewport();
+            if (x - split * width).abs() <= SPLIT_GRAB_DISTANCE {
+                self.dragging_split = true;
+                // Stop the click that ends the drag from picking.
+                self.drag_active = true;
+                return true;
+            }
+        }
+        false
+    }
+
+    fn drag_split_to(&mut self, x: f32) {
+        let (width, _) = self.css_viewport();
+        if width > 0. {
+            self.split = Some((x / width).max(0.).min(1.));
+            self.dirty = true;
+        }
+    }
+
+    fn start_drag(&mut self, x: i32, y: i32) {
+        self.dragging = true;
+        self.drag_active = false;
+        self.drag_start = (x, y);
+    }
+
+    /// Handles mouse movement with the button down, ignoring it until the pointer has left
+    /// the dead zone around where the drag started.
+    fn drag_to(&mut self, x: i32, y: i32, dx: i32, dy: i32) {
+        if !self.drag_active {
+            let (sx, sy) = self.drag_start;
+            let dead_zone = self.settings.drag_dead_zone;
+            if (x - sx) * (x - sx) + (y - sy) * (y - sy) < dead_zone * dead_zone {
+                return;
+            }
+            self.drag_active = true;
+        }
+        self.drag(dx as f32, dy as f32);
+    }
+
+    fn drag(&mut self, dx: f32, dy: f32) {
+        let scale = self.settings.mouse_sensitivity;
+        let exponent = self.settings.mouse_exponent;
+        self.scene.transform = self.scene.transform * Matrix4::<f32>::from(Euler::new(
+            Rad(response_curve(dy, exponent) * scale),
+            Rad(response_curve(dx, exponent) * scale),
+            Rad(0.),
+        ));
+    }
+
+    /// Applies everything the event handlers queued since the last frame.
+    fn handle_input(&mut self) {
+        for input in self.input.take() {
+            match input {
+                Input::KeyDown { code, repeat } => {
+                    self.dirty = true;
+                    self.key_down(&code, repeat);
+                }
+                Input::KeyUp(code) => {
+                    self.dirty = true;
+                    self.key_up(&code);
+                }
+                Input::Blur => self.release_input(),
+                Input::MouseDown { x, y, offset_x } => {
+                    if !self.start_split_drag(offset_x) {
+                        self.start_drag(x, y);
+                    }
+                }
+                Input::MouseUp => {
+                    self.dragging = false;
+                    self.dragging_split = false;
+                }
+                Input::Click { x, y } => {
+                    if self.measuring {
+                        self.measure_click(x, y);
+                        self.dirty = true;
+                    } else if !self.drag_active {
+                        self.pick_click(x, y);
+                    }
+                }
+                Input::Hover { x, y } => {
+                    if self.dragging_split {
+                        self.drag_split_to(x);
+                    } else if self.color_picker && !self.dragging {
+                        self.pick_color(x, y);
+                    }
+                }
+                Input::MouseMove { x, y, dx, dy } => {
+                    if self.dragging {
+                        self.drag_to(x, y, dx, dy);
+                        self.dirty = true;
+                    }
+                }
+                Input::TouchStart(started) => {
+                    for point in started {
+                        self.touches.retain(|touch| touch.id != point.id);
+                        self.touches.push(point);
+                    }
+                }
+                Input::TouchMove(moved) => self.touch_move(&moved),
+                Input::TouchEnd(ended) => {
+                    self.touches
+                        .retain(|touch| ended.iter().all(|point| point.id != touch.id));
+                }
+                Input::Redraw => self.dirty = true,
+                Input::ZigguratLoaded(result) => self.ziggurat_loaded(result),
+            }
+        }
+    }
+
+    /// Rotates the model as a mouse drag would while one finger is down, and zooms by the
+    /// change in spread while two are.
+    fn touch_move(&mut self, moved: &[TouchPoint]) {
+        let before = self.touches.clone();
+        for point in moved {
+            if let Some(touch) = self.touches.iter_mut().find(|touch| touch.id == point.id) {
+                *touch = *point;
+            }
+        }
+        if self.touches.len() == 1 {
+            let (from, to) = (before[0], self.touches[0]);
+            self.drag(to.x - from.x, to.y - from.y);
+            self.dirty = true;
+        } else if self.touches.len() == 2 {
+            let spread = before[0].distance(&before[1]);
+            if spread > 0. {
+                self.view.zoom(self.touches[0].distance(&self.touches[1]) / spread);
+                self.dirty = true;
+            }
+        }
+    }
+
+    fn key_down(&mut self, code: &str, repeat: bool) {
+        let bound = self.settings.key_bindings.lookup(code);
+        if !bound.is_empty() {
+            self.keys |= bound;
+            return;
+        }
+        match code {
+            "KeyL" if !repeat => self.light_auto_orbit = !self.light_auto_orbit,
+            "KeyI" if !repeat => self.toggle_vertex_labels(),
+            "KeyM" if !repeat => self.toggle_measuring(),
+            "KeyZ" if !repeat => self.toggle_split(),
+            "KeyF" if !repeat => self.toggle_wireframe(),
+            "KeyU" if !repeat => self.toggle_frame_graph(),
+            "KeyY" if !repeat => self.toggle_color_picker(),
+            "KeyC" if !repeat => self.toggle_affine_interpolation(),
+            "KeyH" if !repeat => self.show_shadow_map = !self.show_shadow_map,
+            "KeyB" if !repeat => self.cycle_background(),
+            "KeyP" if !repeat => self.cycle_pixel_art(),
+            "KeyK" if !repeat => self.toggle_pick_backend(),
+            "KeyV" if !repeat => self.dump_camera(),
+            "KeyE" if !repeat => self.export_peon(),
+            "KeyN" if !repeat => self.dither = !self.dither,
+            "KeyO" if !repeat => self.show_overdraw = !self.show_overdraw,
+            "KeyQ" if !repeat => {
+                for (name, value) in webgl_info(&self.context) {
+                    console!(log, format!("{}: {}", name, value));
+                }
+            }
+            "KeyR" if !repeat => {
+                let report = analyze(&*self.peon_mesh);
+                console!(log, format!("{:#?}", report));
+            }
+            "Equal" => self.adjust_exposure(1.1),
+            "Minus" => self.adjust_exposure(1. / 1.1),
+            "KeyT" if !repeat => self.toggle_tonemap(),
+            "KeyX" if !repeat => self.toggle_auto_exposure(),
+            "KeyG" if !repeat => self.cycle_normal_display(),
+            "Digit1" => self.adjust_color_scale(vec3(1. / 1.05, 1., 1.05)),
+            "Digit2" => self.adjust_color_scale(vec3(1.05, 1., 1. / 1.05)),
+            "Digit3" => self.adjust_color_scale(vec3(1. / 1.1, 1. / 1.1, 1. / 1.1)),
+            "Digit4" => self.adjust_color_scale(vec3(1.1, 1.1, 1.1)),
+            "Digit0" if !repeat => self.reset_color_scale(),
+            "Digit5" => self.adjust_selected_opacity(-0.1),
+            "Digit6" => self.adjust_selected_opacity(0.1),
+            "Digit7" if !repeat => self.toggle_demo_lights(),
+            // Taken once the next frame has been drawn.
+            "Digit8" if !repeat => self.screenshot_requested = true,
+            "Digit9" if !repeat => self.toggle_axes(),
+            "BracketRight" => self.adjust_peon_brightness(1.2),
+            "BracketLeft" => self.adjust_peon_brightness(1. / 1.2),
+            "Quote" => self.adjust_peon_gamma(1.2),
+            "Semicolon" => self.adjust_peon_gamma(1. / 1.2),
+            "PageUp" => self.adjust_ambient(0.05),
+            "PageDown" => self.adjust_ambient(-0.05),
+            "Home" => self.adjust_point_size(1.25),
+            "End" => self.adjust_point_size(1. / 1.25),
+            "KeyJ" if !repeat => self.shadows = !self.shadows,
+            "Backslash" if !repeat => self.reload_ziggurat(),
+            "Backquote" if !repeat => self.toggle_peon_finish(),
+            "Slash" if !repeat => self.toggle_selected_wire_overlay(),
+            "Enter" if !repeat => self.start_turntable(),
+            "NumpadAdd" => self.adjust_grid(|grid| grid.spacing *= 2.),
+            "NumpadSubtract" => self.adjust_grid(|grid| grid.spacing /= 2.),
+            "NumpadMultiply" => self.adjust_grid(|grid| grid.extent *= 2.),
+            "NumpadDivide" => self.adjust_grid(|grid| grid.extent /= 2.),
+            "Insert" => self.adjust_grid(|grid| grid.major_every += 1),
+            "Delete" => self.adjust_grid(|grid| grid.major_every = grid.major_every.max(1) - 1),
+            _ => {}
+        }
+    }
+
+    /// Starts the turntable demo: frames the ziggurat, clears to a plain background with the
+    /// debug overlays off, and turns the model once around over `Settings::turntable_duration`,
+    /// saving `Settings::turntable_frames` frames at evenly spaced angles as PNGs.  Everything
+    /// goes back as it was afterwards.
+    fn start_turntable(&mut self) {
+        if self.turntable.is_some() {
+            return;
+        }
+        let (top, bottom) = BACKGROUND_PRESETS[0];
+        self.turntable = Some(Turntable {
+            start_time: None,
+            start_transform: self.scene.transform,
+            saved: 0,
+            view: self.view,
+            top_color: mem::replace(&mut self.top_color, top),
+            bottom_color: mem::replace(&mut self.bottom_color, bottom),
+            axes: self.axes.take(),
+            measure_points: mem::replace(&mut self.measure_points, Vec::new()),
+            measure_line: self.measure_line.take(),
+            frame_graph: self.frame_graph.take(),
+            split: self.split.take(),
+            show_shadow_map: mem::replace(&mut self.show_shadow_map, false),
+            show_overdraw: mem::replace(&mut self.show_overdraw, false),
+            wireframe: mem::replace(&mut self.wireframe, false),
+            normal_display: mem::replace(&mut self.normal_display, NormalDisplay::Off),
+        });
+        if let Some(bounds) = self.model_bounds {
+            self.view.frame(bounds);
+        }
+        self.hud.set("turntable", "Recording turntable...");
+        self.dirty = true;
+    }
+
+    /// Turns the model for the frame at `time` while the turntable demo runs, returning the
+    /// number of the frame to save once it's drawn, if one is due.  The model follows the
+    /// clock between saved frames, but each saved frame is at exactly its own angle, however
+    /// the browser's frames happen to fall.
+    fn turn_turntable(&mut self, time: f64) -> Option<u32> {
+        let frames = self.settings.turntable_frames.max(1);
+        let duration = self.settings.turntable_duration as f64;
+        let turntable = self.turntable.as_mut()?;
+        let elapsed = time - *turntable.start_time.get_or_insert(time);
+        let next = turntable.saved as f64 / frames as f64;
+        let (turned, frame) = if elapsed >= next * duration {
+            (next, Some(turntable.saved))
+        } else {
+            (elapsed / duration, None)
+        };
+        let angle = Deg(turned as f32 * 360.);
+        self.scene.transform = Matrix4::from_angle_y(angle) * turntable.start_transform;
+        self.dirty = true;
+        frame
+    }
+
+    /// Counts a turntable frame as saved, ending the demo after the last one.
+    fn turntable_frame_saved(&mut self) {
+        let done = match self.turntable {
+            Some(ref mut turntable) => {
+                turntable.saved += 1;
+                turntable.saved >= self.settings.turntable_frames.max(1)
+            }
+            None => return,
+        };
+        if !done {
+            return;
+        }
+        if let Some(turntable) = self.turntable.take() {
+            self.scene.transform = turntable.start_transform;
+            self.view = turntable.view;
+            self.top_color = turntable.top_color;
+            self.bottom_color = turntable.bottom_color;
+            self.axes = turntable.axes;
+            self.measure_points = turntable.measure_points;
+            self.measure_line = turntable.measure_line;
+            self.frame_graph = turntable.frame_graph;
+            self.split = turntable.split;
+            self.show_shadow_map = turntable.show_shadow_map;
+            self.show_overdraw = turntable.show_overdraw;
+            self.wireframe = turntable.wireframe;
+            self.normal_display = turntable.normal_display;
+        }
+        self.hud.clear("turntable");
+        self.dirty = true;
+    }
+
+    /// Loads the ziggurat again, e.g. to pick up a re-exported model without reloading the
+    /// page.  If that fails, the old one stays.
+    fn reload_ziggurat(&mut self) {
+        self.hud.set("loading", "Reloading ziggurat...");
+        let input = self.input.clone();
+        load_ziggurat(ZigguratSource::new(&self.settings, &self.context), move |result| {
+            input.push(Input::ZigguratLoaded(result))
+        });
+    }
+
+    /// Swaps the reloaded ziggurat's parts in for the old ones, or shows why it couldn't be
+    /// loaded and keeps the old ones.
+    fn ziggurat_loaded(&mut self, result: Result<Vec<ModelPart>, ModelError>) {
+        self.hud.clear("loading");
+        let url = self.settings.ziggurat_url;
+        let parts = match result {
+            Ok(parts) => parts,
+            Err(err) => {
+                report_load_error(&mut self.hud, url, &err);
+                return;
+            }
+        };
+        self.hud.clear(url);
+        match part_summary(&parts) {
+            Some(summary) => self.hud.set("parts", &summary),
+            None => self.hud.clear("parts"),
+        }
+        self.model_bounds = parts_bounds(&parts);
+        let mut nodes = bind_parts(&self.context, self.settings.ziggurat_layout, &parts);
+        let rest = nodes.split_off(1);
+        let first = nodes.pop().expect("models always have at least one part");
+        // The root keeps its transform, and the peon and the rest follow the new parts.
+        let others = self.scene.children.split_off(self.ziggurat_parts - 1);
+        self.scene.name = first.name;
+        self.scene.mesh = first.mesh;
+        self.scene.wire_overlay = first.wire_overlay;
+        self.scene.children = rest;
+        self.scene.children.extend(others);
+        self.ziggurat_parts = parts.len();
+        // The scene's nodes have been renumbered.
+        self.object_opacity.clear();
+        self.selected = None;
+        self.dirty = true;
+    }
+
+    fn key_up(&mut self, code: &str) {
+        self.keys &= !self.settings.key_bindings.lookup(code);
+    }
+
+    fn animate(&mut self, time: f64, rc: Rc<RefCell<Self>>) {
+        self.handle_input();
+        // Input handlers all mark the state dirty, so that doubles as an input signal.
+        if self.dirty || self.dragging || !self.keys.is_empty() {
+            self.last_input = time;
+        }
+        if let Some(timeout) = self.settings.idle_timeout {
+            let idle = time - self.last_input > timeout as f64 && self.turntable.is_none();
+            let interval = 1000. / self.settings.idle_frame_rate as f64;
+            if idle && time - self.time_old < interval {
+                // Skip this frame entirely, leaving `time_old` alone so the next frame that
+                // does run sees the full time elapsed since the last one.
+                self.request_frame(rc);
+                return;
+            }
+        }
+
+        let dt = (time - self.time_old) as f32;
+        self.time_old = time;
+        // Advance in fixed steps so motion doesn't depend on the frame rate, carrying any
+        // remainder over to the next frame.  Capping the backlog stops a long stall from
+        // queueing up more steps than a frame can run.
+        let timestep = self.settings.fixed_timestep;
+        self.accumulator = (self.accumulator + dt).min(self.settings.max_accumulated_time);
+        while self.accumulator >= timestep {
+            self.step(timestep);
+            self.accumulator -= timestep;
+        }
+        // After stepping, so the turntable's angle wins over any keys held while it records.
+        let turntable_frame = self.turn_turntable(time);
+
+        if let Some(ref mut frame_graph) = self.frame_graph {
+            frame_graph.push(dt);
+        }
+        self.update_stats(dt);
+
+        let size = (self.canvas.width(), self.canvas.height());
+        let animating =
+            !self.keys.is_empty() || self.light_auto_orbit || self.frame_graph.is_some();
+        let redraw = !self.settings.redraw_only_when_dirty || self.dirty || animating;
+        if redraw || size != self.drawn_size {
+            self.render_views();
+            self.render();
+            self.dirty = false;
+            self.drawn_size = size;
+            if self.screenshot_requested {
+                self.screenshot_requested = false;
+                self.save_canvas("ziggurat.png");
+            }
+            if let Some(frame) = turntable_frame {
+                self.save_canvas(&format!("turntable-{:03}.png", frame));
+                self.turntable_frame_saved();
+            }
+            if self.auto_exposure && !self.show_overdraw {
+                self.adapt_exposure(dt);
+            }
+        }
+
+        self.request_frame(rc);
+        self.prev_keys = self.keys;
+
+        if let Some(mut on_frame) = self.on_frame.take() {
+            on_frame(self, time);
+            self.on_frame = Some(on_frame);
+        }
+    }
+
+    /// Records a frame that took `dt` milliseconds and shows the smoothed frame rate.
+    fn update_stats(&mut self, dt: f32) {
+        if self.frame_times.len() == STATS_FRAMES {
+            self.frame_times.pop_front();
+        }
+        self.frame_times.push_back(dt);
+        let average = self.frame_times.iter().sum::<f32>() / self.frame_times.len() as f32;
+        let fps = if average > 0. { 1000. / average } else { 0. };
+        self.stats
+            .set_text_content(&format!("{:.0} fps ({:.1} ms)", fps, dt));
+    }
+
+    /// Advances key-driven rotation and the light's movement by `dt` milliseconds.
+    fn step(&mut self, dt: f32) {
+        let held = self.held_keys(dt);
+        let step = (dt * 0.001).min(self.settings.max_frame_rotation);
+        self.scene.transform = self.scene.transform * Matrix4::<f32>::from(Euler::new(
+            Rad(step
+                * (held.contains(Keys::UP) as i8 - held.contains(Keys::DOWN) as i8)
+                    as f32),
+            Rad(step
+                * (held.contains(Keys::RIGHT) as i8 - held.contains(Keys::LEFT) as i8)
+                    as f32),
+            Rad(0.),
+        ));
+
+        let light_direction = self.light_auto_orbit as i8 + self.keys.contains(Keys::LIGHT_CCW)
+            as i8 - self.keys.contains(Keys::LIGHT_CW) as i8;
+        self.light_angle += dt * 0.001 * self.settings.light_orbit_speed * light_direction as f32;
+        let height_direction = self.keys.contains(Keys::LIGHT_UP) as i8
+            - self.keys.contains(Keys::LIGHT_DOWN) as i8;
+        self.light_height +=
+            dt * 0.001 * self.settings.light_height_speed * height_direction as f32;
+        self.light_position = Point3::new(
+            self.settings.light_orbit_radius * self.light_angle.sin(),
+            self.light_height,
+            self.settings.light_orbit_radius * self.light_angle.cos(),
+        );
+    }
+
+    /// Schedules the next call to `animate`, unless the page is hidden and we should pause.
+    fn request_frame(&mut self, rc: Rc<RefCell<Self>>) {
+        if self.settings.pause_when_hidden && document_hidden() {
+            self.input.paused.set(true);
+        } else {
+            window().request_animation_frame(move |time| {
+                rc.borrow_mut().animate(time, rc.clone());
+            });
+        }
+    }
+
+    /// Restarts the frame loop after it paused for a hidden page.  `time_old` is reset to the
+    /// first new frame's timestamp so the time spent hidden doesn't turn into one huge `dt`.
+    fn resume(rc: Rc<RefCell<Self>>) {
+        window().request_animation_frame(move |time| {
+            let mut state = rc.borrow_mut();
+            state.time_old = time;
+            state.dirty = true;
+            state.animate(time, rc.clone());
+        });
+    }
+
+    /// Renders each of `views` from its own camera and copies it into its canvas.  They're
+    /// drawn at the main canvas's size but projected with their own aspect ratio, so the
+    /// stretch into their canvases restores the right proportions.  The copy has to happen
+    /// straight after each render, for the same reason as in `save_canvas`, and the main view
+    /// is rendered over them afterwards.
+    fn render_views(&mut self) {
+        if self.views.is_empty() {
+            return;
+        }
+        let main_view = self.view;
+        let views = mem::replace(&mut self.views, Vec::new());
+        for view in &views {
+            let (w, h) = (view.canvas.width(), view.canvas.height());
+            if w == 0 || h == 0 {
+                continue;
+            }
+            self.view = view.camera;
+            self.render_aspect = Some(w as f32 / h as f32);
+            self.render();
+            js! {
+                @{&view.canvas}.getContext("2d").drawImage(@{&self.canvas}, 0, 0, @{w}, @{h});
+            }
+        }
+        self.view = main_view;
+        self.render_aspect = None;
+        self.views = views;
+    }
+
+    fn render(&mut self) {
+        self.context.enable(gl::DEPTH_TEST);
+        self.context.depth_func(gl::LEQUAL);
+        self.context.clear_color(0.0, 0.0, 0.0, 1.0);
+        self.context.clear_depth(1.0);
+        // PLY exporters (Blender included) write faces counter-clockwise seen from outside, as
+        // do the built-in meshes, so GL's default CCW front face is right.  See
+        // `ParseOptions::fix_winding` for files that don't.
+        self.context.enable(gl::CULL_FACE);
+        self.context.front_face(gl::CCW);
+        self.context.cull_face(gl::BACK);
+
+        // Other programs only use a position attribute, so make sure the mesh attributes left
+        // enabled by the previous frame don't get validated against their draws.
+        self.disable_attribs(&[self.color, self.normal, self.alpha, self.uv]);
+        if let Some(mut shadow_map) = self.shadow_map.take() {
+            shadow_map.render(
+                &self.context,
+                self.light_position,
+                self.settings.shadow_depth_bias,
+                &self.scene_meshes(),
+            );
+            self.shadow_map = Some(shadow_map);
+        }
+
+        if self.settings.reversed_z {
+            // The shadow map above keeps conventional depth; only the main view is reversed.
+            self.context.depth_func(gl::GEQUAL);
+            self.context.clear_depth(0.0);
+        }
+
+        let (w, h) = (self.canvas.width(), self.canvas.height());
+        let proj_matrix = self.projection_matrix();
+
+        if self.show_overdraw {
+            self.overdraw.resize(&self.context, w as i32, h as i32);
+            let view_proj_matrix = proj_matrix * self.view.view_matrix();
+            self.overdraw
+                .render(&self.context, &self.quad, &view_proj_matrix, &self.scene_meshes());
+            return;
+        }
+
+        // In pixel-art mode everything below is drawn at the low resolution and scaled up at
+        // the end; the projection still uses the canvas's aspect ratio, so the stretch back out
+        // restores the right proportions.
+        let (vw, vh) = match self.pixel_art {
+            Some((pw, ph)) => {
+                self.pixel_art_view.bind(&self.context, pw, ph);
+                (pw, ph)
+            }
+            None => {
+                self.context.viewport(0, 0, w as i32, h as i32);
+                (w, h)
+            }
+        };
+        let background = self.bottom_color;
+        self.context
+            .clear_color(background[0], background[1], background[2], 1.0);
+        self.context
+            .clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+        self.context.clear_color(0.0, 0.0, 0.0, 1.0);
+
+        if self.top_color != self.bottom_color {
+            self.background.draw(
+                &self.context,
+                &self.quad,
+                self.top_color,
+                self.bottom_color,
+                self.dither,
+            );
+        }
+
+        match self.split {
+            None => self.draw_scene(&proj_matrix, self.normal_display),
+            Some(split) => {
+                // Draw the scene once per side, each clipped to its half, then a divider.
+                let x = (vw as f32 * split) as i32;
+                self.context.enable(gl::SCISSOR_TEST);
+                self.context.scissor(0, 0, x, vh as i32);
+                self.draw_scene(&proj_matrix, self.normal_display);
+                self.context.scissor(x, 0, vw as i32 - x, vh as i32);
+                self.draw_scene(&proj_matrix, self.split_normal_display());
+                self.context.scissor(x - 1, 0, 2, vh as i32);
+                self.context.clear_color(1.0, 1.0, 1.0, 1.0);
+                self.context.clear(gl::COLOR_BUFFER_BIT);
+                self.context.clear_color(0.0, 0.0, 0.0, 1.0);
+                self.context.disable(gl::SCISSOR_TEST);
+            }
+        }
+
+        if let (true, Some(shadow_map)) = (self.show_shadow_map, self.shadow_map.as_ref()) {
+            self.disable_attribs(&[self.color, self.normal, self.alpha, self.uv]);
+            let size = (vw.min(vh) / 3) as i32;
+            self.context.viewport(0, 0, size, size);
+            self.depth_view
+                .draw(&self.context, &self.quad, &shadow_map.depth_texture);
+            self.context.viewport(0, 0, vw as i32, vh as i32);
+        }
+
+        if self.pixel_art.is_some() {
+            self.disable_attribs(&[self.color, self.normal, self.alpha, self.uv]);
+            self.pixel_art_view.present(&self.context, &self.quad, w, h);
+        }
+
+        if let Some(ref frame_graph) = self.frame_graph {
+            frame_graph.draw(&self.context);
+        }
+
+        if !self.vertex_labels.is_empty() {
+            self.update_vertex_labels();
+        }
+    }
+
+    /// Draws one of `scene_meshes`.  Under a wire overlay, its faces are pushed away from the
+    /// camera with polygon offset (lines aren't offset), so the edges drawn over them don't
+    /// z-fight with them.
+    fn draw_object(
+        &self,
+        mesh: &BoundMesh,
+        model: &Matrix4<f32>,
+        proj_matrix: &Matrix4<f32>,
+        wire_overlay: bool,
+    ) {
+        if !wire_overlay {
+            self.draw_mesh(mesh, model, proj_matrix);
+            return;
+        }
+        // Away is towards 0 with reversed-Z depth.
+        let (factor, units) = WIRE_OVERLAY_OFFSET;
+        let sign = if self.settings.reversed_z { -1. } else { 1. };
+        self.context.enable(gl::POLYGON_OFFSET_FILL);
+        self.context.polygon_offset(sign * factor, sign * units);
+        self.draw_mesh(mesh, model, proj_matrix);
+        self.context.disable(gl::POLYGON_OFFSET_FILL);
+    }
+
+    /// Draws the scene's meshes, markers and measure line with the scene shader.
+    fn draw_scene(&self, proj_matrix: &Matrix4<f32>, normal_display: NormalDisplay) {
+        self.context.use_program(Some(&self.shader.program));
+        self.enable_attribs(&[self.position, self.color, self.normal]);
+        self.context
+            .uniform1i(Some(&self.affine_color), self.affine_interpolation as i32);
+        self.context
+            .uniform1i(Some(&self.normal_display_uniform), normal_display as i32);
+        self.upload_lighting();
+
+        // Opaque objects first, then translucent ones blended over them.  The translucent ones
+        // don't write depth, so they don't hide each other; they aren't sorted either, which
+        // is fine while there are only a couple of objects.
+        self.context.uniform1f(Some(&self.object_alpha), 1.);
+        let overlays = self.scene_wire_overlays();
+        for (i, (mesh, model)) in self.scene_meshes().into_iter().enumerate() {
+            if self.opacity(i) >= 1. && !mesh.has_alpha() {
+                self.draw_object(mesh, &model, proj_matrix, overlays[i]);
+            }
+        }
+        for (i, (mesh, model)) in self.scene_meshes().into_iter().enumerate() {
+            if overlays[i] && mesh.primitive != PrimitiveType::Points {
+                self.draw_wire_overlay(mesh, &model, proj_matrix);
+            }
+        }
+
+        let peon_matrix = self.peon_matrix();
+        for point in &self.measure_points {
+            let model = peon_matrix
+                * Matrix4::from_translation(point.to_vec())
+                * Matrix4::from_scale(0.03);
+            self.draw_mesh(&self.marker, &model, proj_matrix);
+        }
+        if let Some(ref line) = self.measure_line {
+            self.draw_mesh_as(gl::LINES, line, &peon_matrix, proj_matrix);
+        }
+        // Not part of the scene, so it stays put while the model rotates.
+        if let Some(ref axes) = self.axes {
+            self.draw_mesh_as(gl::LINES, axes, &Matrix4::identity(), proj_matrix);
+        }
+
+        let any_translucent = self
+            .scene_meshes()
+            .iter()
+            .enumerate()
+            .any(|(i, &(mesh, _))| self.opacity(i) < 1. || mesh.has_alpha());
+        if any_translucent {
+            self.context.enable(gl::BLEND);
+            self.context.blend_func(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+            self.context.depth_mask(false);
+            for (i, (mesh, model)) in self.scene_meshes().into_iter().enumerate() {
+                let opacity = self.opacity(i);
+                if opacity < 1. || mesh.has_alpha() {
+                    self.context.uniform1f(Some(&self.object_alpha), opacity);
+                    self.draw_object(mesh, &model, proj_matrix, overlays[i]);
+                }
+            }
+            self.context.depth_mask(true);
+            self.context.disable(gl::BLEND);
+        }
+    }
+}
+
+fn main() {
+    stdweb::initialize();
+
+    // Doesn't work because web-sys isn't integrated with stdweb.
+    //let audio = web_sys::AudioContext::new().unwrap();
+    //let source = audio.create_buffer_source().unwrap(); // creates a sound source
+    ////source.buffer = buffer;                    // tell the source which sound to play
+    //source.connect_with_audio_node(&audio.destination());       // connect the source to the context's destination (the speakers)
+    //source.start();
+
+    // Doesn't work due to https://github.com/koute/stdweb/issues/171
+    //let image = ImageElement::new();
+    //image.add_event_listener({
+    //    let image = image.clone();
+    //    move |evt: ResourceLoadEvent| {
+    //        console!(log, "Image loaded");
+    //        console!(log, "Image loaded", image.get_attribute("src"));
+    //    }
+    //});
+    //image.add_event_listener({
+    //    let image = image.clone();
+    //    move |_: ResourceErrorEvent| {
+    //        console!(log, "Image failed to load");
+    //        console!(log, "Image failed to load", image.get_attribute("src"));
+    //    }
+    //});
+    //image.add_event_listener({
+    //    let image = image.clone();
+    //    move |_: ResourceAbortEvent| {
+    //        console!(log, "Image load aborted");
+    //        console!(log, "Image load aborted", image.get_attribute("src"));
+    //    }
+    //});
+    //image.set_src("test.png");
+
+    let canvas: CanvasElement = document()
+        .query_selector("#canvas")
+        .unwrap()
+        .unwrap()
+        .try_into()
+        .unwrap();
+    let context = antialiased_context(&canvas);
+
+    fit_canvas(&canvas);
+
+    window().add_event_listener({
+        let canvas = canvas.clone();
+        move |_: ResizeEvent| fit_canvas(&canvas)
+    });
+
+    let settings = Settings::default();
+    // Hook for code embedding the viewer to run every frame; see `start`.
+    let on_frame: Option<Box<dyn FnMut(&State, f64)>> = None;
+
+    let mut hud = Hud::new(document().query_selector("#hud").unwrap().unwrap());
+    hud.set("loading", "Loading models...");
+
+    let peon_url = settings.peon_url;
+    load_ply(peon_url, ParseOptions::default(), move |peon| {
+        // The peon is edited as a whole, so it can't be split.
+        let peon_mesh = mesh_or_empty(&mut hud, peon_url, peon.and_then(PlyMesh::single));
+        let source = ZigguratSource::new(&settings, &context);
+        let fallback_url = settings.fallback_model_url;
+        let start_with = move |hud: Hud, ziggurat_parts: Vec<ModelPart>| {
+            start(canvas, context, settings, hud, peon_mesh, ziggurat_parts, on_frame)
+        };
+        load_ziggurat(source, move |ziggurat| match (ziggurat, fallback_url) {
+            (Err(err), Some(fallback_url)) => {
+                report_load_error(&mut hud, source.url, &err);
+                let fallback = ZigguratSource {
+                    url: fallback_url,
+                    procedural: None,
+                    ..source
+                };
+                load_ziggurat(fallback, move |fallback| {
+                    let ziggurat_parts = parts_or_empty(&mut hud, fallback_url, fallback);
+                    start_with(hud, ziggurat_parts);
+                });
+            }
+            (ziggurat, _) => {
+                let ziggurat_parts = parts_or_empty(&mut hud, source.url, ziggurat);
+                start_with(hud, ziggurat_parts);
+            }
+        });
+    });
+
+    stdweb::event_loop();
+}
+
+/// Creates the canvas's WebGL context, asking for multisampled antialiasing, which stdweb's
+/// `get_context` has no way to request.  `antialias` is only a hint: browsers without MSAA
+/// (or with it blacklisted for the GPU) hand back an ordinary context instead, so edges stay
+/// jagged but everything else works as before.  That fallback is logged.
+fn antialiased_context(canvas: &CanvasElement) -> WebGLRenderingContext {
+    let context: WebGLRenderingContext = js! {
+        return @{canvas}.getContext("webgl", { antialias: true, depth: true });
+    }.try_into()
+        .unwrap();
+    let antialiased = js! {
+        return @{&context}.getContextAttributes().antialias;
+    };
+    if !antialiased.try_into().unwrap_or(false) {
+        console!(log, "Antialiasing unavailable; edges will be aliased");
+    }
+    context
+}
+
+/// Finds the canvas for one of `Settings::views` and keeps it sized like the main one, or
+/// logs and returns `None` if `selector` doesn't match a canvas.
+fn view_canvas(selector: &str) -> Option<CanvasElement> {
+    let canvas: Option<CanvasElement> = document()
+        .query_selector(selector)
+        .ok()
+        .and_then(|element| element)
+        .and_then(|element| element.try_into().ok());
+    let canvas = match canvas {
+        Some(canvas) => canvas,
+        None => {
+            console!(log, format!("No canvas for view {}", selector));
+            return None;
+        }
+    };
+    fit_canvas(&canvas);
+    window().add_event_listener({
+        let canvas = canvas.clone();
+        move |_: ResizeEvent| fit_canvas(&canvas)
+    });
+    Some(canvas)
+}
+
+/// Sizes the canvas's drawing buffer to its on-screen size in device pixels rather than CSS
+/// pixels, so it isn't rendered at a fraction of the resolution and stretched on HiDPI
+/// displays.  Mouse handling stays in CSS pixels; see `State::css_viewport`.
+fn fit_canvas(canvas: &CanvasElement) {
+    let ratio = window().device_pixel_ratio();
+    canvas.set_width((canvas.offset_width() as f64 * ratio).round() as u32);
+    canvas.set_height((canvas.offset_height() as f64 * ratio).round() as u32);
+}
+
+/// Fetches and parses the PLY file at `url`, then calls `on_load` with the result, split into
+/// chunks if it's too big to index with `u16`.
+fn load_ply<F>(url: &str, options: ParseOptions, on_load: F)
+where
+    F: FnOnce(Result<Vec<PlyMesh>, PlyMeshError>) + 'static,
+{
+    fetch_bytes(url, move |bytes| {
+        on_load(match bytes {
+            Ok(bytes) => PlyMesh::parse_chunks_with_options(&mut bytes.as_slice(), &options),
+            Err(status) => Err(PlyMeshError::Http(status)),
+        })
+    });
+}
+
+/// Like `load_ply`, but splits the file into its parts; see `PlyMesh::parse_parts`.
+fn load_ply_parts<F>(url: &str, options: ParseOptions, on_load: F)
+where
+    F: FnOnce(Result<Vec<PlyPart>, PlyMeshError>) + 'static,
+{
+    fetch_bytes(url, move |bytes| {
+        on_load(match bytes {
+            Ok(bytes) => PlyMesh::parse_parts(&mut bytes.as_slice(), &options),
+            Err(status) => Err(PlyMeshError::Http(status)),
+        })
+    });
+}
+
+/// Fetches and parses the OBJ file at `url`, then calls `on_load` with the result.
+fn load_obj<F>(url: &str, on_load: F)
+where
+    F: FnOnce(Result<ObjMesh, ObjError>) + 'static,
+{
+    fetch_bytes(url, move |bytes| {
+        on_load(match bytes {
+            Ok(bytes) => ObjMesh::parse(&mut bytes.as_slice()),
+            Err(status) => Err(ObjError::Http(status)),
+        })
+    });
+}
+
+/// Fetches the file at `url`, then calls `on_load` with its contents, or with the HTTP status
+/// if the request failed (0 if there was no response at all).
+fn fetch_bytes<F>(url: &str, on_load: F)
+where
+    F: FnOnce(Result<Vec<u8>, u16>) + 'static,
+{
+    let request = XmlHttpRequest::new();
+    // Fetch raw bytes rather than text, which would mangle binary files.
+    js! { @{&request}.responseType = "arraybuffer"; }
+    // Fires once the request has finished, whether it succeeded or not.
+    let mut on_load = Some(on_load);
+    request.add_event_listener({
+        let request = request.clone();
+        move |_: LoadEndEvent| {
+            let on_load = match on_load.take() {
+                Some(on_load) => on_load,
+                None => return,
+            };
+            let status = request.status();
+            let result = if status >= 200 && status < 300 {
+                let response: Result<ArrayBuffer, _> =
+                    js!( return @{&request}.response; ).try_into();
+                // No body at all reads as empty, which the parsers report as such.
+                Ok(response.map_or(Vec::new(), |buffer| buffer.into()))
+            } else {
+                Err(status)
+            };
+            on_load(result);
+        }
+    });
+    let sent = request.open("GET", url).and_then(|_| request.send());
+    if sent.is_err() {
+        if let Some(on_load) = on_load.take() {
+            on_load(Err(0));
+        }
+    }
+}
+
+/// Unwraps a mesh loaded at startup, showing the error and substituting an empty mesh on
+/// failure (there being nothing loaded yet to keep) so the rest of the scene still shows.
+fn mesh_or_empty<M, E>(hud: &mut Hud, url: &'static str, result: Result<M, E>) -> Box<dyn Mesh>
+where
+    M: Mesh + 'static,
+    E: fmt::Display,
+{
+    match result {
+        Ok(mesh) => Box::new(mesh),
+        Err(err) => {
+            report_load_error(hud, url, &err);
+            Box::new(OwnedMesh::default())
+        }
+    }
+}
+
+/// Like `mesh_or_empty`, for a model made of parts.
+fn parts_or_empty(
+    hud: &mut Hud,
+    url: &'static str,
+    result: Result<Vec<ModelPart>, ModelError>,
+) -> Vec<ModelPart> {
+    match result {
+        Ok(parts) => parts,
+        Err(err) => {
+            let empty = mesh_or_empty::<OwnedMesh, _>(hud, url, Err(err));
+            vec![ModelPart::whole(empty)]
+        }
+    }
+}
+
+/// Shows why the model at `url` couldn't be loaded, on a HUD line of its own (keyed by the
+/// URL) as well as in the console.
+fn report_load_error(hud: &mut Hud, url: &'static str, err: &dyn fmt::Display) {
+    let message = format!("Couldn't load {}: {}", url, err);
+    console!(error, &message);
+    hud.set(url, &message);
+}
+
+/// A piece of a loaded model, which gets a scene node of its own; see `bind_parts`.
+struct ModelPart {
+    /// Reported when the part is picked; see `PlyPart::name`.  Empty for models that come in
+    /// one piece.
+    pub name: String,
+    pub mesh: Box<dyn Mesh>,
+    pub material_color: Option<Vector3<f32>>,
+}
+
+impl ModelPart {
+    fn whole(mesh: Box<dyn Mesh>) -> Self {
+        ModelPart {
+            name: String::new(),
+            mesh,
+            material_color: None,
+        }
+    }
+}
+
+impl From<PlyPart> for ModelPart {
+    fn from(part: PlyPart) -> Self {
+        ModelPart {
+            name: part.name,
+            mesh: Box::new(part.mesh),
+            material_color: part.material_color,
+        }
+    }
+}
+
+#[derive(Debug)]
+enum ModelError {
+    Ply(PlyMeshError),
+    Obj(ObjError),
+}
+
+impl fmt::Display for ModelError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ModelError::Ply(ref err) => write!(f, "{}", err),
+            ModelError::Obj(ref err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl Error for ModelError {}
+
+impl From<PlyMeshError> for ModelError {
+    fn from(err: PlyMeshError) -> Self {
+        ModelError::Ply(err)
+    }
+}
+
+impl From<ObjError> for ModelError {
+    fn from(err: ObjError) -> Self {
+        ModelError::Obj(err)
+    }
+}
+
+/// Where `load_ziggurat` gets the ziggurat from.
+#[derive(Clone, Copy)]
+struct ZigguratSource {
+    /// A PLY file, or an OBJ file, told apart by its `.obj` extension.
+    pub url: &'static str,
+    /// Build the ziggurat with these `Ziggurat::new` arguments instead of loading `url`.
+    pub procedural: Option<(u32, f32, f32)>,
+    /// How to parse a PLY file.
+    pub options: ParseOptions,
+}
+
+impl ZigguratSource {
+    fn new(settings: &Settings, context: &WebGLRenderingContext) -> Self {
+        ZigguratSource {
+            url: settings.ziggurat_url,
+            procedural: settings.procedural_ziggurat,
+            // The baked ziggurat has no vertex colors; it's drawn gray.  Big scans can be
+            // drawn whole where 32-bit indices are supported, and are split into chunks where
+            // they aren't.
+            options: ParseOptions {
+                require_colors: false,
+                wide_indices: has_extension(context, "OES_element_index_uint"),
+                flat_shading: settings.flat_shading,
+                normalize: settings.normalize_model,
+                ..ParseOptions::default()
+            },
+        }
+    }
+}
+
+/// Builds or fetches the ziggurat, then calls `on_load` with its parts: one for each part of
+/// a multi-part PLY file (or chunk of a big one), or just one otherwise.
+fn load_ziggurat<F>(source: ZigguratSource, on_load: F)
+where
+    F: FnOnce(Result<Vec<ModelPart>, ModelError>) + 'static,
+{
+    match source.procedural {
+        Some((tiers, base_size, tier_ratio)) => {
+            let mesh = Ziggurat::new(tiers, base_size, tier_ratio);
+            on_load(Ok(vec![ModelPart::whole(Box::new(mesh))]));
+        }
+        None if source.url.ends_with(".obj") => load_obj(source.url, move |mesh| {
+            on_load(match mesh {
+                Ok(mesh) => Ok(vec![ModelPart::whole(Box::new(mesh))]),
+                Err(err) => Err(err.into()),
+            })
+        }),
+        None => load_ply_parts(source.url, source.options, move |parts| {
+            on_load(match parts {
+                Ok(parts) => Ok(parts.into_iter().map(ModelPart::from).collect()),
+                Err(err) => Err(err.into()),
+            })
+        }),
+    }
+}
+
+/// Binds each part of a model as a scene node, named after the part and tinted with its
+/// material's color.
+fn bind_parts(
+    context: &WebGLRenderingContext,
+    layout: VertexLayout,
+    parts: &[ModelPart],
+) -> Vec<SceneNode> {
+    parts
+        .iter()
+        .map(|part| {
+            let mut mesh = match layout {
+                VertexLayout::Separate => part.mesh.bind(context),
+                VertexLayout::Interleaved => part.mesh.bind_interleaved(context),
+            };
+            if let Some(color) = part.material_color {
+                mesh.material_color = color;
+            }
+            let name = if part.name.is_empty() {
+                Cow::Borrowed("ziggurat")
+            } else {
+                Cow::Owned(format!("ziggurat {}", part.name))
+            };
+            SceneNode::new(name, mesh, Matrix4::identity())
+        }).collect()
+}
+
+/// The box around all of a model's parts, or `None` if they have no vertices.
+fn parts_bounds(parts: &[ModelPart]) -> Option<(Point3<f32>, Point3<f32>)> {
+    parts
+        .iter()
+        .filter_map(|part| part.mesh.bounding_box())
+        .fold(None, |bounds, b| Some(bounds.map_or(b, |a| union_boxes(a, b))))
+}
+
+/// Lists the names of a model's parts for the HUD, or `None` if it's all one unnamed piece.
+fn part_summary(parts: &[ModelPart]) -> Option<String> {
+    let mut names: Vec<&str> = Vec::new();
+    for part in parts.iter().filter(|part| !part.name.is_empty()) {
+        if !names.contains(&&*part.name) {
+            names.push(&part.name);
+        }
+    }
+    if names.is_empty() {
+        None
+    } else {
+        Some(format!("Parts: {}", names.join(", ")))
+    }
+}
+
+/// Sets the bound 2D texture's anisotropic filtering level, as described for
+/// `Settings::texture_anisotropy`.  The generated bindings don't include extensions, so this
+/// goes via JavaScript.
+fn set_anisotropy(context: &WebGLRenderingContext, level: Option<f32>) {
+    js! {
+        var gl = @{context};
+        var ext = gl.getExtension("EXT_texture_filter_anisotropic")
+            || gl.getExtension("WEBKIT_EXT_texture_filter_anisotropic");
+        if (ext) {
+            var max = gl.getParameter(ext.MAX_TEXTURE_MAX_ANISOTROPY_EXT);
+            var level = @{level};
+            level = level === null ? max : Math.max(1, Math.min(level, max));
+            gl.texParameterf(gl.TEXTURE_2D, ext.TEXTURE_MAX_ANISOTROPY_EXT, level);
+        }
+    }
+}
+
+/// Starts loading the image at `url` into a new texture, calling `on_load` once it arrives.
+/// Until then the texture is a single white texel, so it can be drawn with straight away.
+/// `anisotropy` is as for `Settings::texture_anisotropy`.
+fn load_texture<F>(
+    context: &WebGLRenderingContext,
+    url: &str,
+    anisotropy: Option<f32>,
+    on_load: F,
+) -> WebGLTexture
+where
+    F: FnOnce() + 'static,
+{
+    let texture = context.create_texture().unwrap();
+    context.bind_texture(gl::TEXTURE_2D, Some(&texture));
+    // No mipmaps, and clamped rather than repeating, so images needn't be powers of two.
+    context.tex_parameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
+    context.tex_parameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+    context.tex_parameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+    context.tex_parameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+    set_anisotropy(context, anisotropy);
+    context.tex_image2_d(
+        gl::TEXTURE_2D,
+        0,
+        gl::RGBA as i32,
+        1,
+        1,
+        0,
+        gl::RGBA,
+        gl::UNSIGNED_BYTE,
+        Some(&[255u8, 255, 255, 255][..]),
+    );
+
+    // The listeners go on via JavaScript, since stdweb's image events don't fire (see main),
+    // and so does the upload, since the bindings can't take an image as pixel data.
+    let image = ImageElement::new();
+    js! {
+        var gl = @{context};
+        var image = @{&image};
+        var texture = @{&texture};
+        var on_load = @{Once(on_load)};
+        image.onload = function() {
+            gl.bindTexture(gl.TEXTURE_2D, texture);
+            // Texture coordinates (in OBJ and PLY alike) put v = 0 at the bottom of the image.
+            gl.pixelStorei(gl.UNPACK_FLIP_Y_WEBGL, true);
+            gl.texImage2D(gl.TEXTURE_2D, 0, gl.RGBA, gl.RGBA, gl.UNSIGNED_BYTE, image);
+            gl.pixelStorei(gl.UNPACK_FLIP_Y_WEBGL, false);
+            on_load();
+        };
+        image.onerror = function() {
+            console.error("Couldn't load " + image.src);
+            on_load.drop();
+        };
+    }
+    image.set_src(url);
+    texture
+}
+
+/// Sets up the scene and event handlers once the models have loaded.
+///
+/// `on_frame`, if given, is called at the end of every frame with the state and the frame's
+/// timestamp, e.g. to keep an external UI in sync.  It must not mutate GL state (bindings,
+/// enabled capabilities, the current program, ...): the next frame assumes whatever the last
+/// one left behind.
+fn start(
+    canvas: CanvasElement,
+    context: WebGLRenderingContext,
+    settings: Settings,
+    mut hud: Hud,
+    peon_mesh: Box<dyn Mesh>,
+    ziggurat_parts: Vec<ModelPart>,
+    on_frame: Option<Box<dyn FnMut(&State, f64)>>,
+) {
+    hud.clear("loading");
+    if let Some(summary) = part_summary(&ziggurat_parts) {
+        hud.set("parts", &summary);
+    }
+    let mut peon = peon_mesh.bind_with(&context, settings.peon_color_format);
+    peon.set_finish(settings.peon_finish);
+    let ziggurat_bounds = parts_bounds(&ziggurat_parts);
+    let mut ziggurat = bind_parts(&context, settings.ziggurat_layout, &ziggurat_parts).into_iter();
+    let mut scene = ziggurat.next().expect("models always have at least one part");
+    // Any further parts are drawn along with the first, wherever it goes.
+    scene.children.extend(ziggurat);
+    scene = scene
+        .with_child(SceneNode::new("peon", peon, Matrix4::from_translation(PEON_OFFSET)));
+    if let Some(subdivisions) = settings.ground_plane {
+        // Just below the ziggurat's base, so the two don't fight over depth.
+        let transform = Matrix4::from_translation(vec3(0., -0.01, 0.))
+            * Matrix4::from_scale(GROUND_PLANE_SIZE);
+        let mut plane = Plane::new(subdivisions);
+        plane.triangle_strip = settings.ground_plane_strip;
+        let ground = plane.bind(&context);
+        scene = scene.with_child(SceneNode::new("ground", ground, transform));
+    }
+    if settings.tinted_cubes {
+        for &(x, tint) in &[(-4., vec3(1., 0.45, 0.4)), (4., vec3(0.4, 0.6, 1.))] {
+            let mut cube = Cube.bind(&context);
+            cube.material_color = tint;
+            let transform = Matrix4::from_translation(vec3(x, 0.5, 0.)) * Matrix4::from_scale(0.5);
+            scene = scene.with_child(SceneNode::new("cube", cube, transform));
+        }
+    }
 
     let shader = Shader::new(
         &context,
-        r#"
-            attribute vec3 position;
-            attribute vec3 normal;
-            uniform mat4 Pmatrix;
-            uniform mat4 Vmatrix;
-            uniform mat4 Mmatrix;
-            attribute vec3 color;
-            varying vec3 vColor;
-            varying vec3 vNormal;
-            varying vec3 vFragPos;
-
-            void main() {
-                vFragPos = vec3(Mmatrix * vec4(position, 1.));
-                gl_Position = Pmatrix*Vmatrix*vec4(vFragPos, 1.);
-                vNormal = vec3(Mmatrix * vec4(normal, 1.));
-                vColor = color;
-            }
-        "#,
-        r#"
-            precision mediump float;
-            varying vec3 vColor;
-            varying vec3 vNormal;
-            varying vec3 vFragPos;
-
-            void main() {
-                float diffuse = max(dot(vNormal, normalize(vec3(0., 0., 6.) - vFragPos)), 0.0);
-                gl_FragColor = vec4(vColor * (0.5 + 0.5 * diffuse), 1.0);
-            }
-        "#,
+        include_str!("../shaders/scene.vert.glsl"),
+        include_str!("../shaders/scene.frag.glsl"),
     );
 
     /* ====== Associating attributes to vertex shader =====*/
@@ -453,53 +5936,929 @@ fn main() {
     let m_matrix = context
         .get_uniform_location(&shader.program, "Mmatrix")
         .unwrap();
+    let n_matrix = context
+        .get_uniform_location(&shader.program, "Nmatrix")
+        .unwrap();
+    let normal_display_uniform = context
+        .get_uniform_location(&shader.program, "normalDisplay")
+        .unwrap();
+    let affine_color = context
+        .get_uniform_location(&shader.program, "affineColor")
+        .unwrap();
+    let light_uniforms = |name: &str| -> Vec<WebGLUniformLocation> {
+        (0..MAX_LIGHTS)
+            .map(|i| {
+                context
+                    .get_uniform_location(&shader.program, &format!("{}[{}]", name, i))
+                    .unwrap()
+            }).collect()
+    };
+    let light_positions = light_uniforms("lightPositions");
+    let light_colors = light_uniforms("lightColors");
+    let num_lights = context
+        .get_uniform_location(&shader.program, "numLights")
+        .unwrap();
+    let view_pos = context
+        .get_uniform_location(&shader.program, "viewPos")
+        .unwrap();
+    let shininess = context
+        .get_uniform_location(&shader.program, "shininess")
+        .unwrap();
+    let light_matrix = context
+        .get_uniform_location(&shader.program, "lightMatrix")
+        .unwrap();
+    let shadows_uniform = context
+        .get_uniform_location(&shader.program, "shadows")
+        .unwrap();
+    let shadow_map_uniform = context
+        .get_uniform_location(&shader.program, "shadowMap")
+        .unwrap();
+    let shadow_bias = context
+        .get_uniform_location(&shader.program, "shadowBias")
+        .unwrap();
+    let shadow_texel_size = context
+        .get_uniform_location(&shader.program, "shadowTexelSize")
+        .unwrap();
+    let exposure_uniform = context
+        .get_uniform_location(&shader.program, "exposure")
+        .unwrap();
+    let tonemap_operator = context
+        .get_uniform_location(&shader.program, "tonemapOperator")
+        .unwrap();
+    let dither_uniform = context
+        .get_uniform_location(&shader.program, "dither")
+        .unwrap();
+    let color_scale_uniform = context
+        .get_uniform_location(&shader.program, "colorScale")
+        .unwrap();
+    let ambient_uniform = context
+        .get_uniform_location(&shader.program, "ambient")
+        .unwrap();
+    let ambient_color = context
+        .get_uniform_location(&shader.program, "ambientColor")
+        .unwrap();
+    let wire_overlay_uniform = context
+        .get_uniform_location(&shader.program, "wireOverlay")
+        .unwrap();
+    let wire_color = context
+        .get_uniform_location(&shader.program, "wireColor")
+        .unwrap();
+    let point_size_uniform = context
+        .get_uniform_location(&shader.program, "pointSize")
+        .unwrap();
+    let round_points = context
+        .get_uniform_location(&shader.program, "roundPoints")
+        .unwrap();
+    let object_alpha = context
+        .get_uniform_location(&shader.program, "objectAlpha")
+        .unwrap();
+    let material_color = context
+        .get_uniform_location(&shader.program, "materialColor")
+        .unwrap();
+    let specular_color = context
+        .get_uniform_location(&shader.program, "specularColor")
+        .unwrap();
+    let textured = context
+        .get_uniform_location(&shader.program, "textured")
+        .unwrap();
+    let base_texture = context
+        .get_uniform_location(&shader.program, "baseTexture")
+        .unwrap();
+
+    let position = attrib_location(&context, &shader.program, "position");
+    let color = attrib_location(&context, &shader.program, "color");
+    let alpha = attrib_location(&context, &shader.program, "alpha");
+    let uv = attrib_location(&context, &shader.program, "uv");
+    let normal = attrib_location(&context, &shader.program, "normal");
 
-    let position = context.get_attrib_location(&shader.program, "position") as u32;
-    let color = context.get_attrib_location(&shader.program, "color") as u32;
-    let normal = context.get_attrib_location(&shader.program, "normal") as u32;
+    let labels_container = document().query_selector("#labels").unwrap().unwrap();
+    let marker = Cube.bind(&context);
+    let quad = FullScreenQuad::new(&context);
+    let shadow_map = ShadowMap::new(&context, settings.shadow_map_size);
+    let depth_view = DepthView::new(&context);
+    let overdraw = OverdrawView::new(&context);
+    let background = GradientBackground::new(&context);
+    let pixel_art_view = PixelArtView::new(&context);
+    let id_buffer = IdBuffer::new(&context);
+    let (top_color, bottom_color) = BACKGROUND_PRESETS[0];
+    let light_height = settings.light_orbit_height;
+    let ambient = settings.ambient;
+    let point_size = settings.point_size;
+    let grid = settings.grid;
+    let ground_texture = settings.ground_texture;
+    let anisotropy = settings.texture_anisotropy;
+
+    let mut view = Camera {
+        eye: Point3::new(0., 0., 6.),
+        target: Point3::new(0., 0., 0.),
+        up: vec3(0., 1., 0.),
+        fovy: 80.,
+        near: 1.,
+        far: 100.,
+    };
+    if let (true, Some(bounds)) = (settings.frame_model, ziggurat_bounds) {
+        view.frame(bounds);
+    }
+    let views = settings
+        .views
+        .iter()
+        .filter_map(|&(selector, eye)| {
+            let mut camera = Camera {
+                eye: Point3::from(eye),
+                ..view
+            };
+            if let (true, Some(bounds)) = (settings.frame_model, ziggurat_bounds) {
+                camera.frame(bounds);
+            }
+            view_canvas(selector).map(|canvas| View { canvas, camera })
+        })
+        .collect();
 
+    let input = Rc::new(InputQueue::default());
     let state = Rc::new(RefCell::new(State {
         time_old: 0.0,
-        mov_matrix: Matrix4::from_scale(1.),
-        view_matrix: Matrix4::from_translation(vec3(0., 0., -6.)),
+        settings,
+        view,
+        views,
+        render_aspect: None,
         canvas,
         context,
         shader,
         position,
         color,
+        alpha,
+        uv,
         normal,
         p_matrix,
         v_matrix,
         m_matrix,
-        ziggurat,
-        peon,
+        n_matrix,
+        normal_display_uniform,
+        normal_display: NormalDisplay::Off,
+        affine_color,
+        affine_interpolation: false,
+        light_positions,
+        light_colors,
+        num_lights,
+        view_pos,
+        shininess,
+        light_matrix,
+        shadows_uniform,
+        shadow_map_uniform,
+        shadow_bias,
+        shadow_texel_size,
+        shadows: true,
+        exposure_uniform,
+        tonemap_operator,
+        exposure: 1.,
+        tonemap: Tonemap::Reinhard,
+        dither_uniform,
+        auto_exposure: false,
+        dither: true,
+        color_scale_uniform,
+        color_scale: vec3(1., 1., 1.),
+        ambient_uniform,
+        ambient_color,
+        wire_overlay_uniform,
+        wire_color,
+        ambient,
+        point_size_uniform,
+        round_points,
+        point_size,
+        object_alpha,
+        material_color,
+        specular_color,
+        textured,
+        base_texture,
+        object_opacity: Vec::new(),
+        selected: None,
+        scene,
+        ziggurat_parts: ziggurat_parts.len(),
+        peon_mesh,
+        labels_container,
+        vertex_labels: Vec::new(),
+        hud,
+        marker,
+        measuring: false,
+        color_picker: false,
+        wireframe: false,
+        frame_graph: None,
+        axes: None,
+        grid,
+        stats: document().query_selector("#stats").unwrap().unwrap(),
+        frame_times: VecDeque::with_capacity(STATS_FRAMES),
+        measure_points: Vec::new(),
+        measure_line: None,
+        light_position: Point3::new(0., 0., 6.),
+        extra_lights: Vec::new(),
+        light_angle: 0.,
+        light_height,
+        light_auto_orbit: false,
+        accumulator: 0.,
+        quad,
+        shadow_map,
+        depth_view,
+        show_shadow_map: false,
+        overdraw,
+        show_overdraw: false,
+        background,
+        background_preset: 0,
+        top_color,
+        bottom_color,
+        id_buffer: Some(id_buffer),
+        pixel_art_view,
+        pixel_art: None,
+        pixel_art_preset: 0,
         keys: Keys::empty(),
         prev_keys: Keys::empty(),
+        key_hold_times: [0.; 4],
+        dragging: false,
+        drag_active: false,
+        drag_start: (0, 0),
+        split: None,
+        dragging_split: false,
+        touches: Vec::new(),
+        on_frame,
+        dirty: true,
+        screenshot_requested: false,
+        turntable: None,
+        model_bounds: ziggurat_bounds,
+        drawn_size: (0, 0),
+        input: input.clone(),
+        last_input: 0.,
     }));
 
+    if let Some(url) = ground_texture {
+        let texture = load_texture(&state.borrow().context, url, anisotropy, {
+            let input = input.clone();
+            move || input.push(Input::Redraw)
+        });
+        if let Some(ground) = state.borrow_mut().scene.find_mut("ground") {
+            ground.mesh.texture = Some(texture);
+        }
+    }
+
+    let query: String = js!( return window.location.search; ).try_into().unwrap_or_default();
+    if let Some(snippet) = CameraSnippet::parse(&query) {
+        state.borrow_mut().apply_camera_snippet(&snippet);
+    }
+
+    // The handlers below only queue their events, for the next frame to apply.  A frame
+    // holds the state borrowed throughout, so nothing else may borrow it.
     window().add_event_listener({
-        let state = state.clone();
-        move |evt: KeyDownEvent| match evt.code().as_str() {
-            "KeyA" => state.borrow_mut().keys |= Keys::LEFT,
-            "KeyW" => state.borrow_mut().keys |= Keys::UP,
-            "KeyS" => state.borrow_mut().keys |= Keys::DOWN,
-            "KeyD" => state.borrow_mut().keys |= Keys::RIGHT,
-            _ => {}
+        let input = input.clone();
+        move |evt: KeyDownEvent| {
+            input.push(Input::KeyDown {
+                code: evt.code(),
+                repeat: evt.repeat(),
+            })
         }
     });
 
     window().add_event_listener({
+        let input = input.clone();
+        move |evt: KeyUpEvent| input.push(Input::KeyUp(evt.code()))
+    });
+
+    window().add_event_listener({
+        let input = input.clone();
+        move |_: BlurEvent| input.push(Input::Blur)
+    });
+
+    let on_visibility_change = {
         let state = state.clone();
-        move |evt: KeyUpEvent| match evt.code().as_str() {
-            "KeyA" => state.borrow_mut().keys &= !Keys::LEFT,
-            "KeyW" => state.borrow_mut().keys &= !Keys::UP,
-            "KeyS" => state.borrow_mut().keys &= !Keys::DOWN,
-            "KeyD" => state.borrow_mut().keys &= !Keys::RIGHT,
-            _ => {}
+        let input = input.clone();
+        move || {
+            if !document_hidden() && input.paused.replace(false) {
+                State::resume(state.clone());
+            }
+        }
+    };
+    js! {
+        document.addEventListener("visibilitychange", @{on_visibility_change});
+    }
+
+    let canvas = state.borrow().canvas.clone();
+    canvas.add_event_listener({
+        let input = input.clone();
+        move |evt: MouseDownEvent| {
+            if evt.button() == MouseButton::Left {
+                input.push(Input::MouseDown {
+                    x: evt.client_x(),
+                    y: evt.client_y(),
+                    offset_x: evt.offset_x() as f32,
+                });
+            }
+        }
+    });
+
+    canvas.add_event_listener({
+        let input = input.clone();
+        move |evt: ClickEvent| {
+            input.push(Input::Click {
+                x: evt.offset_x() as f32,
+                y: evt.offset_y() as f32,
+            })
+        }
+    });
+
+    canvas.add_event_listener({
+        let input = input.clone();
+        move |evt: MouseMoveEvent| {
+            input.push(Input::Hover {
+                x: evt.offset_x() as f32,
+                y: evt.offset_y() as f32,
+            })
+        }
+    });
+
+    // Touches are tracked by finger, so each event only reports the fingers it changed.
+    // Cancelling the defaults stops the browser scrolling or zooming the page instead.
+    canvas.add_event_listener({
+        let input = input.clone();
+        move |evt: TouchStart| {
+            evt.prevent_default();
+            input.push(Input::TouchStart(TouchPoint::from_touches(evt.changed_touches())));
+        }
+    });
+
+    canvas.add_event_listener({
+        let input = input.clone();
+        move |evt: TouchMove| {
+            evt.prevent_default();
+            input.push(Input::TouchMove(TouchPoint::from_touches(evt.changed_touches())));
+        }
+    });
+
+    canvas.add_event_listener({
+        let input = input.clone();
+        move |evt: TouchEnd| {
+            evt.prevent_default();
+            input.push(Input::TouchEnd(TouchPoint::from_touches(evt.changed_touches())));
+        }
+    });
+
+    canvas.add_event_listener({
+        let input = input.clone();
+        move |evt: TouchCancel| {
+            input.push(Input::TouchEnd(TouchPoint::from_touches(evt.changed_touches())));
+        }
+    });
+
+    window().add_event_listener({
+        let input = input.clone();
+        move |evt: MouseUpEvent| {
+            if evt.button() == MouseButton::Left {
+                input.push(Input::MouseUp);
+            }
+        }
+    });
+
+    window().add_event_listener({
+        let input = input.clone();
+        move |evt: MouseMoveEvent| {
+            input.push(Input::MouseMove {
+                x: evt.client_x(),
+                y: evt.client_y(),
+                dx: evt.movement_x(),
+                dy: evt.movement_y(),
+            })
         }
     });
 
     state.borrow_mut().animate(0., state.clone());
+}
 
-    stdweb::event_loop();
+// These need a browser (or Node, for the ones without GL), so run them with `cargo web test`.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_parts_splits_suffixed_elements() {
+        let ply = "ply\n\
+                   format ascii 1.0\n\
+                   element vertex_hull 3\n\
+                   property float x\n\
+                   property float y\n\
+                   property float z\n\
+                   element face_hull 1\n\
+                   property list uchar int vertex_indices\n\
+                   element vertex_deck 4\n\
+                   property float x\n\
+                   property float y\n\
+                   property float z\n\
+                   element face_deck 1\n\
+                   property list uchar int vertex_indices\n\
+                   end_header\n\
+                   0 0 0\n\
+                   1 0 0\n\
+                   0 1 0\n\
+                   3 0 1 2\n\
+                   0 0 1\n\
+                   1 0 1\n\
+                   1 1 1\n\
+                   0 1 1\n\
+                   4 0 1 2 3\n";
+        let options = ParseOptions {
+            require_colors: false,
+            ..ParseOptions::default()
+        };
+        let parts = PlyMesh::parse_parts(&mut ply.as_bytes(), &options).unwrap();
+        let names: Vec<&str> = parts.iter().map(|part| &*part.name).collect();
+        assert_eq!(names, ["hull", "deck"]);
+        assert_eq!(parts[0].mesh.vertices.len(), 3 * 3);
+        assert_eq!(parts[0].mesh.indices, [0, 1, 2]);
+        assert_eq!(parts[1].mesh.vertices.len(), 4 * 3);
+        assert_eq!(parts[1].mesh.indices, [0, 1, 2, 0, 2, 3]);
+    }
+
+    #[test]
+    fn parse_parts_splits_by_material() {
+        let ply = "ply\n\
+                   format ascii 1.0\n\
+                   element vertex 4\n\
+                   property float x\n\
+                   property float y\n\
+                   property float z\n\
+                   element face 3\n\
+                   property list uchar int vertex_indices\n\
+                   property int material_index\n\
+                   element material 2\n\
+                   property uchar diffuse_red\n\
+                   property uchar diffuse_green\n\
+                   property uchar diffuse_blue\n\
+                   end_header\n\
+                   0 0 0\n\
+                   1 0 0\n\
+                   1 1 0\n\
+                   0 1 0\n\
+                   3 0 1 2 1\n\
+                   3 0 2 3 0\n\
+                   3 1 2 3 -1\n\
+                   255 0 0\n\
+                   0 0 255\n";
+        let parts = PlyMesh::parse_parts(&mut ply.as_bytes(), &ParseOptions::default()).unwrap();
+        let names: Vec<&str> = parts.iter().map(|part| &*part.name).collect();
+        // The face with material -1 has none, rather than wrapping around to material 0.
+        assert_eq!(names, ["material1", "material0", ""]);
+        assert_eq!(parts[0].material_color, Some(vec3(0., 0., 1.)));
+        assert_eq!(parts[0].mesh.indices, [0, 1, 2]);
+        assert_eq!(parts[1].material_color, Some(vec3(1., 0., 0.)));
+        assert_eq!(parts[1].mesh.indices, [0, 2, 3]);
+        assert_eq!(parts[2].material_color, None);
+        assert_eq!(parts[2].mesh.indices, [1, 2, 3]);
+    }
+
+    #[test]
+    fn parse_empty_ply() {
+        match PlyMesh::parse(&mut &b""[..]) {
+            Err(PlyMeshError::Empty) => {}
+            other => panic!("expected PlyMeshError::Empty, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn parse_signed_char_properties() {
+        let ply = "ply\n\
+                   format ascii 1.0\n\
+                   element vertex 3\n\
+                   property char x\n\
+                   property char y\n\
+                   property char z\n\
+                   property char red\n\
+                   property char green\n\
+                   property char blue\n\
+                   element face 1\n\
+                   property list uchar int vertex_indices\n\
+                   end_header\n\
+                   -1 -2 0 127 -5 0\n\
+                   1 -2 0 127 -5 0\n\
+                   0 3 0 127 -5 0\n\
+                   3 0 1 2\n";
+        let mesh = PlyMesh::parse(&mut ply.as_bytes()).unwrap();
+        // Coordinates are sign-extended rather than wrapping around to large positives.
+        assert_eq!(mesh.vertices, [-1., -2., 0., 1., -2., 0., 0., 3., 0.]);
+        // The positive half of the range scales to [0, 1], and negative values clamp to 0.
+        assert_eq!(&mesh.colors[..3], [1., 0., 0.]);
+    }
+
+    #[test]
+    fn cube_round_trips_through_ascii_ply() {
+        let mut ply = Vec::new();
+        write_ascii_ply(&Cube, &mut ply).unwrap();
+        let mesh = PlyMesh::parse_with_options(&mut &ply[..], &ParseOptions::default()).unwrap();
+        assert_eq!(mesh.vertices(), Cube.vertices());
+        assert_eq!(mesh.indices(), Cube.indices());
+    }
+
+    #[test]
+    fn camera_unprojects_projected_points() {
+        let camera = Camera {
+            eye: Point3::new(1., 2., 6.),
+            target: Point3::new(0., 0., 0.),
+            up: Vector3::new(0., 1., 0.),
+            fovy: 60.,
+            near: 1.,
+            far: 100.,
+        };
+        let viewport = (640., 480.);
+        let points = [
+            (0., 0., 0.),
+            (1., -1., 0.5),
+            (-2., 1.5, -3.),
+            (0.25, 0.75, 2.),
+        ];
+        for &(x, y, z) in &points {
+            let world = Point3::new(x, y, z);
+            let screen = camera.project(world, viewport).unwrap();
+            let ray = camera.unproject((screen.x, screen.y), viewport).unwrap();
+            // The unprojected ray passes through the original point, in front of the camera.
+            let along = (world - ray.origin).dot(ray.direction);
+            let off_ray = ray.origin + ray.direction * along - world;
+            assert!(along > 0., "{:?} is behind the ray", world);
+            let distance = off_ray.magnitude();
+            assert!(distance < 1e-3, "{:?} is {} off the ray", world, distance);
+        }
+    }
+
+    #[test]
+    fn parse_renormalizes_scaled_normals() {
+        let ply = "ply\n\
+                   format ascii 1.0\n\
+                   element vertex 3\n\
+                   property float x\n\
+                   property float y\n\
+                   property float z\n\
+                   property float nx\n\
+                   property float ny\n\
+                   property float nz\n\
+                   element face 1\n\
+                   property list uchar int vertex_indices\n\
+                   end_header\n\
+                   0 0 0 0 0 2\n\
+                   1 0 0 0 0 0.5\n\
+                   0 1 0 0 0 3\n\
+                   3 0 1 2\n";
+        let options = ParseOptions {
+            require_colors: false,
+            ..ParseOptions::default()
+        };
+        let mesh = PlyMesh::parse_with_options(&mut ply.as_bytes(), &options).unwrap();
+        for n in mesh.normals.chunks(3) {
+            let length = vec3(n[0], n[1], n[2]).magnitude();
+            assert!((length - 1.).abs() < 1e-6, "{:?}", n);
+        }
+    }
+
+    #[test]
+    fn parse_fans_quads_into_triangles() {
+        let ply = "ply\n\
+                   format ascii 1.0\n\
+                   element vertex 4\n\
+                   property float x\n\
+                   property float y\n\
+                   property float z\n\
+                   element face 1\n\
+                   property list uchar int vertex_indices\n\
+                   end_header\n\
+                   0 0 0\n\
+                   1 0 0\n\
+                   1 1 0\n\
+                   0 1 0\n\
+                   4 0 1 2 3\n";
+        let options = ParseOptions {
+            require_colors: false,
+            ..ParseOptions::default()
+        };
+        let mesh = PlyMesh::parse_with_options(&mut ply.as_bytes(), &options).unwrap();
+        assert_eq!(mesh.indices, [0, 1, 2, 0, 2, 3]);
+    }
+
+    /// A WebGL context on a fresh, detached canvas.
+    fn test_context() -> WebGLRenderingContext {
+        ::stdweb::initialize();
+        let canvas: CanvasElement = document()
+            .create_element("canvas")
+            .unwrap()
+            .try_into()
+            .unwrap();
+        antialiased_context(&canvas)
+    }
+
+    #[test]
+    fn dropping_bound_mesh_deletes_buffers() {
+        let context = test_context();
+        let mesh = Cube.bind(&context);
+        let buffers = [mesh.vertex_buffer.clone(), mesh.index_buffer.clone()];
+        assert!(buffers.iter().all(|b| context.is_buffer(Some(b))));
+        drop(mesh);
+        assert!(buffers.iter().all(|b| !context.is_buffer(Some(b))));
+    }
+
+    #[test]
+    fn shader_compile_error_includes_log() {
+        let context = test_context();
+        let vertex = "void main() { gl_Position = vec4(0.0); }";
+        let fragment = "precision mediump float;\n\
+                        void main() { gl_FragColor = undeclaredColor; }";
+        match Shader::compile(&context, vertex, fragment) {
+            Err(ShaderError::Compile("fragment", ref log)) if log.contains("undeclaredColor") => {}
+            Err(err) => panic!("unexpected error: {}", err),
+            Ok(_) => panic!("broken shader compiled"),
+        }
+    }
+
+    #[test]
+    fn parse_binary_matches_ascii() {
+        let header = |format: &str| {
+            format!(
+                "ply\n\
+                 format {} 1.0\n\
+                 element vertex 3\n\
+                 property float x\n\
+                 property float y\n\
+                 property float z\n\
+                 element face 1\n\
+                 property list uchar int vertex_indices\n\
+                 end_header\n",
+                format
+            )
+        };
+        let vertices: [f32; 9] = [0., 0., 0., 1.5, 0., 0., 0., -2.25, 0.];
+        let ascii = header("ascii") + "0 0 0\n1.5 0 0\n0 -2.25 0\n3 0 1 2\n";
+        let mut binary = header("binary_little_endian").into_bytes();
+        for &v in &vertices {
+            let bits = v.to_bits();
+            binary.extend((0..4).map(|i| (bits >> (i * 8)) as u8));
+        }
+        binary.push(3);
+        for &i in &[0u32, 1, 2] {
+            binary.extend((0..4).map(|b| (i >> (b * 8)) as u8));
+        }
+
+        let options = ParseOptions {
+            require_colors: false,
+            ..ParseOptions::default()
+        };
+        let ascii = PlyMesh::parse_with_options(&mut ascii.as_bytes(), &options).unwrap();
+        let binary = PlyMesh::parse_with_options(&mut &binary[..], &options).unwrap();
+        assert_eq!(binary.vertices, vertices);
+        assert_eq!(binary.vertices, ascii.vertices);
+        assert_eq!(binary.indices, ascii.indices);
+    }
+
+    #[test]
+    fn parse_double_coordinates() {
+        let ply = "ply\n\
+                   format ascii 1.0\n\
+                   element vertex 3\n\
+                   property double x\n\
+                   property double y\n\
+                   property double z\n\
+                   property double nx\n\
+                   property double ny\n\
+                   property double nz\n\
+                   element face 1\n\
+                   property list uchar int vertex_indices\n\
+                   end_header\n\
+                   0.5 -1.25 2 0 0 1\n\
+                   1.5 -1.25 2 0 0 1\n\
+                   0.5 0.75 2 0 0 1\n\
+                   3 0 1 2\n";
+        let options = ParseOptions {
+            require_colors: false,
+            ..ParseOptions::default()
+        };
+        let mesh = PlyMesh::parse_with_options(&mut ply.as_bytes(), &options).unwrap();
+        assert_eq!(
+            mesh.vertices,
+            [0.5, -1.25, 2., 1.5, -1.25, 2., 0.5, 0.75, 2.]
+        );
+        assert_eq!(mesh.normals, [0., 0., 1., 0., 0., 1., 0., 0., 1.]);
+    }
+
+    #[test]
+    fn parse_integer_coordinates() {
+        let ply = "ply\n\
+                   format ascii 1.0\n\
+                   element vertex 3\n\
+                   property short x\n\
+                   property int y\n\
+                   property uchar z\n\
+                   property char nx\n\
+                   property char ny\n\
+                   property char nz\n\
+                   element face 1\n\
+                   property list uchar int vertex_indices\n\
+                   end_header\n\
+                   -3 -700 2 0 -1 0\n\
+                   4 -700 2 0 -1 0\n\
+                   -3 -700 200 0 -1 0\n\
+                   3 0 1 2\n";
+        let options = ParseOptions {
+            require_colors: false,
+            ..ParseOptions::default()
+        };
+        let mesh = PlyMesh::parse_with_options(&mut ply.as_bytes(), &options).unwrap();
+        assert_eq!(
+            mesh.vertices,
+            [-3., -700., 2., 4., -700., 2., -3., -700., 200.]
+        );
+        assert_eq!(mesh.normals, [0., -1., 0., 0., -1., 0., 0., -1., 0.]);
+    }
+
+    #[test]
+    fn plane_vertex_and_index_counts() {
+        let plane = Plane::new(2);
+        assert_eq!(plane.vertices().len() / 3, 9);
+        assert_eq!(plane.indices().len(), 24);
+    }
+
+    #[test]
+    fn parse_texcoords_under_either_name() {
+        let options = ParseOptions {
+            require_colors: false,
+            ..ParseOptions::default()
+        };
+        for &(u, v) in &[("u", "v"), ("s", "t")] {
+            let ply = format!(
+                "ply\n\
+                 format ascii 1.0\n\
+                 element vertex 3\n\
+                 property float x\n\
+                 property float y\n\
+                 property float z\n\
+                 property float {}\n\
+                 property float {}\n\
+                 element face 1\n\
+                 property list uchar int vertex_indices\n\
+                 end_header\n\
+                 0 0 0 0 0\n\
+                 1 0 0 1 0\n\
+                 0 1 0 0 0.5\n\
+                 3 0 1 2\n",
+                u, v
+            );
+            let mesh = PlyMesh::parse_with_options(&mut ply.as_bytes(), &options).unwrap();
+            assert_eq!(mesh.texcoords(), [0., 0., 1., 0., 0., 0.5], "{}/{}", u, v);
+        }
+    }
+
+    /// An uncolored mesh with just the given vertices and `wide_indices`.
+    fn bare_mesh(vertices: Vec<f32>, wide_indices: Vec<u32>) -> PlyMesh {
+        PlyMesh {
+            vertices,
+            normals: Vec::new(),
+            colors: Vec::new(),
+            alphas: Vec::new(),
+            texcoords: Vec::new(),
+            indices: Vec::new(),
+            wide_indices,
+            origin_offset: Vector3::new(0., 0., 0.),
+            origin_scale: 1.,
+        }
+    }
+
+    /// The positions of every triangle's corners, in order.
+    fn triangle_positions(mesh: &PlyMesh) -> Vec<f32> {
+        (0..mesh.index_count() / 3)
+            .flat_map(|t| mesh.triangle(t * 3).to_vec())
+            .flat_map(|i| mesh.vertices[i as usize * 3..i as usize * 3 + 3].to_vec())
+            .collect()
+    }
+
+    #[test]
+    fn into_chunks_splits_big_meshes() {
+        // A strip of triangles along the x axis, with more vertices than u16s can index.
+        let count = MAX_CHUNK_VERTICES as u32 + 1000;
+        let vertices = (0..count)
+            .flat_map(|i| vec![i as f32, (i % 2) as f32, 0.])
+            .collect();
+        let indices = (0..count - 2).flat_map(|i| vec![i, i + 1, i + 2]).collect();
+        let mesh = bare_mesh(vertices, indices);
+        let original = triangle_positions(&mesh);
+
+        let chunks = mesh.into_chunks(false);
+        assert!(chunks.len() > 1);
+        for chunk in &chunks {
+            assert!(chunk.vertices.len() / 3 <= MAX_CHUNK_VERTICES);
+            assert!(chunk.wide_indices.is_empty());
+        }
+        let triangles: usize = chunks.iter().map(|chunk| chunk.index_count() / 3).sum();
+        assert_eq!(triangles, count as usize - 2);
+        // Compared without assert_eq!, which would print every coordinate on failure.
+        let chunked: Vec<f32> = chunks.iter().flat_map(triangle_positions).collect();
+        assert!(chunked == original, "chunks differ from the original");
+    }
+
+    #[test]
+    fn obj_cube() {
+        let obj = "# A unit cube\n\
+                   v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\n\
+                   v 0 0 1\nv 1 0 1\nv 1 1 1\nv 0 1 1\n\
+                   f 1 4 3 2\nf 5 6 7 8\nf 1 2 6 5\n\
+                   f 2 3 7 6\nf 3 4 8 7\nf 4 1 5 8\n";
+        let mesh = ObjMesh::parse(&mut obj.as_bytes()).unwrap();
+        assert_eq!(mesh.vertices().len() / 3, 8);
+        assert_eq!(&mesh.vertices()[..3], [0., 0., 0.]);
+        assert_eq!(mesh.indices().len(), 36);
+        assert!(mesh.indices().iter().all(|&i| i < 8));
+        assert_eq!(mesh.normals().len(), mesh.vertices().len());
+    }
+
+    #[test]
+    fn cube_bounding_box() {
+        assert_eq!(
+            Cube.bounding_box(),
+            Some((Point3::new(-1., -1., -1.), Point3::new(1., 1., 1.)))
+        );
+    }
+
+    #[test]
+    fn normalize_centers_and_scales() {
+        // A box from (2, 4, 6) to (6, 5, 7), longest along x.
+        let corners = [2., 4., 6., 6., 5., 7., 2., 5., 7., 6., 4., 6.];
+        let mut mesh = bare_mesh(corners.to_vec(), vec![0, 1, 2, 0, 3, 1]);
+        mesh.normalize();
+        let (min, max) = mesh.bounding_box().unwrap();
+        let size = max - min;
+        let center = min + size / 2.;
+        assert!(center.to_vec().magnitude() < 1e-6, "center {:?}", center);
+        let longest = size.x.max(size.y).max(size.z);
+        assert!((longest - 1.).abs() < 1e-6, "size {:?}", size);
+        assert!(mesh.vertices.iter().all(|v| v.abs() <= 0.5));
+        assert_eq!(mesh.origin_scale, 4.);
+    }
+
+    #[test]
+    fn flat_shading_uses_face_normals() {
+        // Two triangles sharing an edge, at an angle to each other.
+        let vertices = vec![0., 0., 0., 1., 0., 0., 1., 1., 0., 0., 1., 1.];
+        let flat = bare_mesh(vertices, vec![0, 1, 2, 0, 2, 3]).to_flat_shaded();
+        assert_eq!(flat.vertices.len() / 3, flat.index_count());
+        assert_eq!(flat.index_count(), 6);
+        let tilted = vec3(1., -1., 1.).normalize();
+        for (i, n) in flat.normals.chunks(3).enumerate() {
+            let expected = if i < 3 { vec3(0., 0., 1.) } else { tilted };
+            let n = vec3(n[0], n[1], n[2]);
+            assert!((n - expected).magnitude() < 1e-6, "vertex {}: {:?}", i, n);
+        }
+    }
+
+    #[test]
+    fn parse_rejects_out_of_range_indices() {
+        let ply = "ply\n\
+                   format ascii 1.0\n\
+                   element vertex 3\n\
+                   property float x\n\
+                   property float y\n\
+                   property float z\n\
+                   element face 1\n\
+                   property list uchar int vertex_indices\n\
+                   end_header\n\
+                   0 0 0\n\
+                   1 0 0\n\
+                   0 1 0\n\
+                   4 0 1 2 3\n";
+        let options = ParseOptions {
+            require_colors: false,
+            ..ParseOptions::default()
+        };
+        match PlyMesh::parse_with_options(&mut ply.as_bytes(), &options) {
+            Err(PlyMeshError::IndexOutOfRange { index: 3, .. }) => {}
+            other => panic!("expected IndexOutOfRange, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn write_ascii_ply_writes_wide_triangles() {
+        let mesh = bare_mesh(vec![0., 0., 0., 1., 0., 0., 0., 1., 0.], vec![0, 1, 2]);
+        let mut ply = Vec::new();
+        write_ascii_ply(&mesh, &mut ply).unwrap();
+        let ply = String::from_utf8(ply).unwrap();
+        assert!(ply.contains("element face 1\n"), "{}", ply);
+        assert!(ply.ends_with("3 0 1 2\n"), "{}", ply);
+    }
+
+    #[test]
+    fn plane_strip_matches_triangles() {
+        let mut plane = Plane::new(3);
+        assert!(plane.strip_indices().is_empty());
+        plane.triangle_strip = true;
+        let strip = plane.strip_indices();
+        assert!(strip.len() < plane.indices().len());
+        // Every other triangle of a strip winds the other way, so is read back swapped.
+        let triangles: Vec<u16> = (0..strip.len() - 2)
+            .map(|k| (k, strip[k], strip[k + 1], strip[k + 2]))
+            .filter(|&(_, a, b, c)| a != b && b != c && a != c)
+            .flat_map(|(k, a, b, c)| {
+                if k % 2 == 0 {
+                    vec![a, b, c]
+                } else {
+                    vec![b, a, c]
+                }
+            }).collect();
+        assert_eq!(triangles, plane.indices());
+    }
 }